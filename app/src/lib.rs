@@ -3,15 +3,23 @@ use std::error::Error;
 use std::ffi::CString;
 use std::os::raw::c_void;
 use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 use std::{env, vec};
 use tauri::image::Image;
-use tauri::{AppHandle, Manager};
+use tauri::{AppHandle, Emitter, Manager};
 use tauri_plugin_clipboard_manager::ClipboardExt;
 use window_vibrancy::apply_mica;
 use windows::Win32::UI::WindowsAndMessaging::{
     SystemParametersInfoA, SPIF_UPDATEINIFILE, SPI_SETDESKWALLPAPER,
 };
 
+use xpic::catalog::{Entry, Search};
+use xpic::config::{Config, SaveOptions};
+use xpic::dedup::{self, Dedup};
+use xpic::library::Library;
+use xpic::palette::Palette;
+use xpic::retention::{self, Pruned};
+use xpic::spotlight::watch::Watcher;
 use xpic::{bing, spotlight};
 
 fn get_cache_dir() -> PathBuf {
@@ -20,14 +28,30 @@ fn get_cache_dir() -> PathBuf {
     })
 }
 
+/// Returns the settings in `config.toml` next to the cache, or the defaults.
+fn get_config() -> Config {
+    let path = get_cache_dir().with_file_name("config.toml");
+
+    if !path.exists() {
+        return Config::default();
+    }
+
+    Config::load(&path).unwrap_or_else(|e| {
+        eprintln!("failed to load {}: {}", path.display(), e);
+        Config::default()
+    })
+}
+
 async fn cache_images() {
     let dir = get_cache_dir();
+    let config = get_config();
 
     futures::future::join_all(vec![
         {
             let dir = dir.to_owned();
+            let config = config.clone();
             tokio::spawn(async move {
-                if let Err(e) = spotlight::copy_images_to(&dir) {
+                if let Err(e) = spotlight::copy_images_to(&dir, config.filter.as_ref(), config.dedup, &config.save) {
                     eprintln!(
                         "failed to copy Windows Spotlight wallpapers to {}: {}",
                         dir.display(),
@@ -39,7 +63,7 @@ async fn cache_images() {
         {
             let dir = dir.to_owned();
             tokio::spawn(async move {
-                if let Err(e) = bing::copy_images_to(&dir).await {
+                if let Err(e) = bing::copy_images_to(&dir, config.filter.as_ref(), config.dedup, &config.save).await {
                     eprintln!("failed to copy Bing wallpapers to {}:{}", dir.display(), e);
                 }
             })
@@ -48,29 +72,122 @@ async fn cache_images() {
         .await;
 }
 
-fn get_cached_images() -> Vec<PathBuf> {
-    let pattern = get_cache_dir().join("*.*");
+/// Keeps each logical wallpaper once, by its best copy, and drops hidden ones.
+fn representatives(mut entries: Vec<Entry>) -> Vec<Entry> {
+    entries.retain(|entry| !entry.hidden && entry.group_id.is_none_or(|group_id| group_id == entry.id));
+    entries
+}
+
+fn get_cached_images() -> Result<Vec<Entry>, Box<dyn Error>> {
+    Ok(representatives(Library::open(get_cache_dir())?.list()?))
+}
+
+#[tauri::command]
+async fn get_wallpapers() -> Result<Vec<Entry>, String> {
+    get_cached_images().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn search_wallpapers(search: Search) -> Result<Vec<Entry>, String> {
+    Library::open(get_cache_dir())
+        .and_then(|library| library.search(&search))
+        .map(representatives)
+        .map_err(|e| e.to_string())
+}
 
-    glob::glob(pattern.to_string_lossy().as_ref())
-        .unwrap()
-        .filter_map(Result::ok)
-        .collect::<Vec<_>>()
+/// Returns the path of a cached thumbnail of a wallpaper, generating it if needed.
+#[tauri::command]
+async fn get_thumbnail(path: String) -> Result<PathBuf, String> {
+    // Decoding and resizing take a while, so they are kept off the async runtime.
+    tauri::async_runtime::spawn_blocking(move || {
+        Library::open(get_cache_dir())
+            .and_then(|library| get_config().thumbnail.get(&library, &library.entry(&path)?))
+            .map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
 }
 
+/// Returns the colors of a wallpaper, extracting them if needed.
 #[tauri::command]
-async fn get_wallpapers() -> Vec<String> {
-    get_cached_images()
-        .into_iter()
-        .map(|path| path.to_string_lossy().to_string())
-        .collect::<Vec<_>>()
+async fn get_palette(path: String) -> Result<Palette, String> {
+    Library::open(get_cache_dir())
+        .and_then(|library| library.palette(&library.entry(&path)?))
+        .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-async fn update_wallpapers() -> Vec<String> {
+async fn set_favorite(path: String, favorite: bool) -> Result<(), String> {
+    Library::open(get_cache_dir())
+        .and_then(|library| library.catalog().set_favorite(library.entry(&path)?.id, favorite))
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn set_hidden(path: String, hidden: bool) -> Result<(), String> {
+    Library::open(get_cache_dir())
+        .and_then(|library| library.catalog().set_hidden(library.entry(&path)?.id, hidden))
+        .map_err(|e| e.to_string())
+}
+
+/// Replaces the tags of a wallpaper.
+#[tauri::command]
+async fn set_tags(path: String, tags: Vec<String>) -> Result<(), String> {
+    Library::open(get_cache_dir())
+        .and_then(|library| {
+            let tags = tags.into_iter().filter(|tag| !tag.trim().is_empty()).collect::<Vec<_>>();
+            library.catalog().set_tags(library.entry(&path)?.id, &tags)
+        })
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn update_wallpapers() -> Result<Vec<Entry>, String> {
     cache_images().await;
+
+    // Catalog wallpapers cached before the catalog existed, then link copies of the same picture.
+    if let Err(e) = Library::open(get_cache_dir()).and_then(|library| {
+        library.scan()?;
+        dedup::dedup_library(&library, Dedup::default(), false)
+    }) {
+        eprintln!("failed to scan {}: {}", get_cache_dir().display(), e);
+    }
+
+    if get_config().retention.is_some_and(|retention| retention.auto) {
+        prune_wallpapers(false).await?;
+    }
+
     get_wallpapers().await
 }
 
+/// Prunes the cache by the retention rules of the config, and returns what was, or with `dry_run` would be, pruned.
+#[tauri::command]
+async fn prune_wallpapers(dry_run: bool) -> Result<Vec<Pruned>, String> {
+    let retention = get_config().retention.unwrap_or_default();
+
+    Library::open(get_cache_dir())
+        .and_then(|library| retention::prune(&library, &retention, None, dry_run))
+        .map_err(|e| e.to_string())
+}
+
+fn watch_spotlight(app_handle: AppHandle) -> Option<Watcher> {
+    let dir = get_cache_dir();
+
+    spotlight::watch::watch(
+        &dir,
+        None,
+        SaveOptions::default(),
+        spotlight::watch::DEFAULT_DEBOUNCE,
+        move |path| {
+            if let Err(err) = app_handle.emit("wallpapers-changed", path.to_string_lossy()) {
+                eprintln!("failed to emit wallpapers-changed: {err}");
+            }
+        },
+    )
+    .map_err(|err| eprintln!("failed to watch Windows Spotlight wallpapers: {err}"))
+    .ok()
+}
+
 #[tauri::command]
 async fn set_as_desktop_wallpaper(path: String) {
     let path_ = CString::new(path.clone()).unwrap();
@@ -128,11 +245,20 @@ pub fn run() {
 
             window.set_decorations(true)?;
 
+            app.manage(Mutex::new(watch_spotlight(app.handle().clone())));
+
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
             get_wallpapers,
+            search_wallpapers,
+            get_thumbnail,
+            get_palette,
+            set_favorite,
+            set_hidden,
+            set_tags,
             update_wallpapers,
+            prune_wallpapers,
             set_as_desktop_wallpaper,
             show_path_in_file_manager,
             copy_image,
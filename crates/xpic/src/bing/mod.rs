@@ -1,7 +1,15 @@
 mod query;
 
 use crate::bing::query::{query, ImageInfo, Query};
+use crate::config::SaveOptions;
+use crate::dedup::{self, Dedup};
+use crate::filter::Filter;
+use crate::library::Library;
+use crate::metadata::{Metadata, Source};
+use crate::resize::Focus;
+use crate::tone::Tone;
 use crate::util;
+use crate::util::{Report, Saved};
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 use std::error::Error;
@@ -16,6 +24,46 @@ pub struct Image {
     pub date: String,
     pub title: String,
     pub copyright: String,
+    /// Headline of the day.
+    pub headline: String,
+    /// Page with more about the picture.
+    pub link: String,
+    pub wallpaper: bool,
+    pub dark: bool,
+    /// Where the subject is, from the hotspots or else from `top` and `bot`.
+    pub focus: Option<Focus>,
+}
+
+/// Returns where the subject of an image is: the average of its hotspots, which mark points of interest in percent of
+/// the image size. Without hotspots, `top` and `bot`, which tell whether the subject is in the upper or lower part of
+/// the image, give a weaker hint of the height of the subject only.
+fn hint(info: &ImageInfo) -> Option<Focus> {
+    let hotspots = info
+        .hotspots
+        .iter()
+        .filter_map(|hotspot| {
+            let x = hotspot.get("locx")?.as_f64()?;
+            let y = hotspot.get("locy")?.as_f64()?;
+            Some(Focus {
+                x: (x / 100.0).clamp(0.0, 1.0),
+                y: (y / 100.0).clamp(0.0, 1.0),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    if hotspots.is_empty() {
+        return match (info.top > 0, info.bottom > 0) {
+            (true, false) => Some(Focus { x: 0.5, y: 0.33 }),
+            (false, true) => Some(Focus { x: 0.5, y: 0.67 }),
+            _ => None,
+        };
+    }
+
+    let n = hotspots.len() as f64;
+    Some(Focus {
+        x: hotspots.iter().map(|focus| focus.x).sum::<f64>() / n,
+        y: hotspots.iter().map(|focus| focus.y).sum::<f64>() / n,
+    })
 }
 
 impl TryFrom<ImageInfo> for Image {
@@ -31,19 +79,25 @@ impl TryFrom<ImageInfo> for Image {
 
         let captures = re.captures(&info.copyright).ok_or("")?;
 
+        let focus = hint(&info);
         let r = Self {
             url: Url::parse("https://www.bing.com/")?
                 .join(&info.url)?,
             date: info.start_date,
             title: captures["title"].to_string(),
             copyright: captures["copyright"].to_string(),
+            headline: info.title,
+            link: info.copyright_link,
+            wallpaper: info.wallpaper,
+            dark: info.dark != 0,
+            focus,
         };
 
         Ok(r)
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Default, PartialEq)]
 pub struct ImageDetail {
     pub name: String,
     pub market: String,
@@ -54,20 +108,6 @@ pub struct ImageDetail {
     pub extension: String,
 }
 
-impl Default for ImageDetail {
-    fn default() -> Self {
-        Self {
-            name: String::default(),
-            market: String::default(),
-            number: 0,
-            uhd: false,
-            width: 0,
-            height: 0,
-            extension: String::default(),
-        }
-    }
-}
-
 impl FromStr for ImageDetail {
     type Err = Box<dyn Error>;
 
@@ -129,42 +169,179 @@ impl Image {
     pub fn detail(&self) -> Result<ImageDetail, Box<dyn Error>> {
         self.id().as_deref().unwrap_or("").parse()
     }
-}
 
+    pub fn metadata(&self) -> Metadata {
+        let mut metadata = Metadata::new(Source::Bing, self.id().unwrap_or_default());
+        metadata.title = Some(self.title.clone());
+        metadata.copyright = Some(self.copyright.clone());
+        metadata.description = Some(self.headline.clone()).filter(|headline| !headline.is_empty());
+        metadata.date = Some(self.date.clone());
+        metadata.wallpaper = Some(self.wallpaper);
+        metadata.dark = Some(self.dark);
+        metadata.tone = Some(Tone::from_hint(self.dark));
+        metadata.link = Some(self.link.clone()).filter(|link| !link.is_empty());
+        metadata.url = Some(self.url.to_string());
+        metadata.focus = self.focus;
 
-pub async fn get_images() -> Result<Vec<Url>, Box<dyn Error>> {
+        if let Ok(detail) = self.detail() {
+            metadata.market = Some(detail.market).filter(|market| !market.is_empty());
+
+            // UHD images have no dimensions in their id.
+            if detail.width > 0 && detail.height > 0 {
+                metadata.width = Some(detail.width as u32);
+                metadata.height = Some(detail.height as u32);
+            }
+        }
+
+        metadata
+    }
+}
+
+/// Returns urls of images matching the filter.
+pub async fn get_images(filter: &Filter) -> Result<Vec<Url>, Box<dyn Error>> {
     Ok(query(Query::default())
         .await?
         .into_iter()
         .filter_map(|info| Image::try_from(info).ok())
+        .filter(|image| filter.matches(&image.metadata()))
         .map(|image| image.url)
         .collect::<Vec<_>>())
 }
 
-/// Copies images to a specified directory.
-pub async fn copy_images_to<P: AsRef<Path>>(dst: P) -> Result<(), Box<dyn Error>> {
+/// Copies images matching `filter` to a specified directory and records them in the catalog.
+pub async fn copy_images_to<P: AsRef<Path>>(
+    dst: P,
+    filter: Option<&Filter>,
+    dedup: Option<Dedup>,
+    save: &SaveOptions,
+) -> Result<Report, Box<dyn Error>> {
     let dst = dst.as_ref();
+    let filter = filter.cloned().unwrap_or_default();
+    let library = Library::open(dst)?;
 
-    fs::create_dir_all(dst)
-        .map_err(|err| format!("failed to create {}: {}", dst.display(), err))?;
+    // Images are downloaded first, and named and filed once their content and dimensions are known.
+    let downloads = library.downloads_dir();
+    fs::create_dir_all(&downloads)?;
 
     let tasks = query(Query::default())
         .await?
         .into_iter()
         .filter_map(|image| {
             let image = Image::try_from(image).ok()?;
-            let dst = dst.join(image.id()?);
-            if dst.exists() {
+            if !filter.matches(&image.metadata()) {
                 return None;
             }
 
+            let id = image.id()?;
+            if library.catalog().is_pruned(Source::Bing, &id).unwrap_or(false) {
+                return None;
+            }
+            // Saved images may have been renamed or converted since.
+            let existing = library.find_by_source(Source::Bing, &id).ok().flatten();
+            let dst = downloads.join(id);
+
             Some(tokio::spawn(async move {
-                if let Err(e) = util::download_file(&image.url, dst).await {
-                    eprintln!("failed to download {}: {}", image.url, e);
+                if let Some(entry) = existing {
+                    return (image, Ok(Saved::Existing(entry.path)));
                 }
+
+                let result = util::download_file(&image.url, dst)
+                    .await
+                    .map_err(|e| format!("failed to download {}: {}", image.url, e));
+                (image, result)
             }))
         });
 
-    futures::future::join_all(tasks).await;
-    Ok(())
+    let mut report = Report::default();
+
+    for result in futures::future::join_all(tasks).await {
+        let (image, saved) = match result {
+            Ok((image, Ok(saved))) => (image, saved),
+            Ok((_, Err(err))) => {
+                report.failed += 1;
+                eprintln!("{err}");
+                continue;
+            }
+            Err(err) => {
+                report.failed += 1;
+                eprintln!("failed to download image: {err}");
+                continue;
+            }
+        };
+
+let metadata = image.metadata();
+        let saved = if saved.path().starts_with(&downloads) {
+            match library.place(saved.path(), &metadata, save.template.as_ref(), save.layout, false) {
+                Ok(saved) => saved,
+                Err(err) => {
+                    report.failed += 1;
+                    eprintln!("failed to name {}: {}", saved.path().display(), err);
+                    continue;
+                }
+            }
+        } else {
+            saved
+        };
+
+        match library.store(metadata, &saved, save) {
+            Ok(_) => report.add(&saved),
+            Err(err) => {
+                report.failed += 1;
+                eprintln!("failed to add {} to catalog: {}", saved.path().display(), err);
+            }
+        }
+    }
+
+    if let Some(dedup) = dedup {
+        dedup::dedup_library(&library, dedup, false)?;
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(hotspots: serde_json::Value) -> ImageInfo {
+        serde_json::from_value(serde_json::json!({
+            "startdate": "20240101",
+            "fullstartdate": "202401010800",
+            "enddate": "20240102",
+            "url": "/th?id=OHR.Example_EN-US1234567890_1920x1080.jpg",
+            "urlbase": "/th?id=OHR.Example_EN-US1234567890",
+            "copyright": "Example (© Photographer)",
+            "copyrightlink": "https://www.bing.com/search?q=example",
+            "title": "Example",
+            "quiz": "/search?q=Bing+homepage+quiz",
+            "wp": true,
+            "hsh": "0",
+            "drk": 1,
+            "top": 1,
+            "bot": 0,
+            "hs": hotspots,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_hint() {
+        let focus = hint(&info(serde_json::json!([
+            { "locx": 20, "locy": 40 },
+            { "locx": 60, "locy": 80 },
+            { "locx": 150, "locy": 30 },
+            { "desc": "no location" },
+        ])))
+        .unwrap();
+        assert!((focus.x - 0.6).abs() < 1e-9);
+        assert!((focus.y - 0.5).abs() < 1e-9);
+
+        // Without hotspots, `top` and `bot` tell the height of the subject.
+        let mut image = info(serde_json::json!([]));
+        assert_eq!(hint(&image), Some(Focus { x: 0.5, y: 0.33 }));
+        (image.top, image.bottom) = (0, 1);
+        assert_eq!(hint(&image), Some(Focus { x: 0.5, y: 0.67 }));
+        (image.top, image.bottom) = (0, 0);
+        assert!(hint(&image).is_none());
+    }
 }
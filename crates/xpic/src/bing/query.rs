@@ -59,17 +59,6 @@ where
     Ok(i.map(|i| i == 1))
 }
 
-impl Query {
-    pub fn new(format: &'static str, index: usize, number: usize) -> Self {
-        Self {
-            format,
-            index,
-            number,
-            ..Self::default()
-        }
-    }
-}
-
 impl Default for Query {
     fn default() -> Self {
         Self {
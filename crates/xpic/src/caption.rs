@@ -0,0 +1,401 @@
+use ab_glyph::{Font, FontVec, Glyph, PxScale, ScaleFont, point};
+use image::{DynamicImage, Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::derived;
+use crate::metadata::Metadata;
+use crate::palette::Color;
+use crate::util;
+
+/// Suffix of the names of captioned copies.
+const SUFFIX: &str = "caption";
+
+/// Fonts tried when no font file is configured.
+const SYSTEM_FONTS: &[&str] = &[
+    "C:\\Windows\\Fonts\\segoeui.ttf",
+    "C:\\Windows\\Fonts\\arial.ttf",
+    "/System/Library/Fonts/Helvetica.ttc",
+    "/Library/Fonts/Arial.ttf",
+    "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf",
+    "/usr/share/fonts/TTF/DejaVuSans.ttf",
+    "/usr/share/fonts/dejavu/DejaVuSans.ttf",
+    "/usr/share/fonts/truetype/noto/NotoSans-Regular.ttf",
+    "/usr/share/fonts/noto/NotoSans-Regular.ttf",
+];
+
+/// Size of the credit line relative to the title.
+const CREDIT_SCALE: f32 = 0.75;
+
+/// Opacity of drop shadows and backdrops.
+const BACKGROUND_ALPHA: f32 = 0.55;
+
+/// Corner of an image.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    #[default]
+    BottomRight,
+}
+
+impl FromStr for Corner {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "top-left" => Ok(Corner::TopLeft),
+            "top-right" => Ok(Corner::TopRight),
+            "bottom-left" => Ok(Corner::BottomLeft),
+            "bottom-right" => Ok(Corner::BottomRight),
+            _ => Err(format!("unknown corner: {s}")),
+        }
+    }
+}
+
+impl fmt::Display for Corner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Corner::TopLeft => write!(f, "top-left"),
+            Corner::TopRight => write!(f, "top-right"),
+            Corner::BottomLeft => write!(f, "bottom-left"),
+            Corner::BottomRight => write!(f, "bottom-right"),
+        }
+    }
+}
+
+/// What keeps a caption readable on a busy picture.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Background {
+    #[default]
+    Shadow,
+    /// A translucent box behind the text.
+    Backdrop,
+    None,
+}
+
+impl FromStr for Background {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "shadow" => Ok(Background::Shadow),
+            "backdrop" => Ok(Background::Backdrop),
+            "none" => Ok(Background::None),
+            _ => Err(format!("unknown background: {s}")),
+        }
+    }
+}
+
+impl fmt::Display for Background {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Background::Shadow => write!(f, "shadow"),
+            Background::Backdrop => write!(f, "backdrop"),
+            Background::None => write!(f, "none"),
+        }
+    }
+}
+
+/// The title and credit of a wallpaper rendered onto a copy of it.
+///
+/// ```toml
+/// [caption]
+/// font = "/usr/share/fonts/truetype/noto/NotoSans-Regular.ttf"
+/// size = 2.5
+/// corner = "bottom-left"
+/// background = "backdrop"
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Caption {
+    /// TrueType or OpenType font file. A common system font is used if unset.
+    pub font: Option<PathBuf>,
+    /// Height of the title in percent of the image height.
+    pub size: f32,
+    pub corner: Corner,
+    pub background: Background,
+    /// Text color. White or black, whichever contrasts more with the picture behind the text, if unset.
+    pub color: Option<Color>,
+}
+
+impl Default for Caption {
+    fn default() -> Self {
+        Self {
+            font: None,
+            size: 2.5,
+            corner: Corner::default(),
+            background: Background::default(),
+            color: None,
+        }
+    }
+}
+
+/// A line of text laid out from the origin, with its width.
+struct Line {
+    glyphs: Vec<Glyph>,
+    width: f32,
+    ascent: f32,
+    height: f32,
+}
+
+impl Caption {
+    fn load_font(&self) -> Result<FontVec, Box<dyn Error>> {
+        let path = match &self.font {
+            Some(font) => font.clone(),
+            None => SYSTEM_FONTS
+                .iter()
+                .map(PathBuf::from)
+                .find(|font| font.exists())
+                .ok_or("no font found, set the font of the caption")?,
+        };
+
+        let data = fs::read(&path).map_err(|e| format!("failed to read font {}: {}", path.display(), e))?;
+        FontVec::try_from_vec_and_index(data, 0)
+            .map_err(|e| format!("failed to load font {}: {}", path.display(), e).into())
+    }
+
+    /// Returns where the captioned copy of `path` is saved: next to it, e.g. `image_caption.jpg`.
+    pub fn path(path: impl AsRef<Path>) -> PathBuf {
+        derived::path(path, SUFFIX)
+    }
+
+    /// Returns a copy of an image with the lines rendered in the corner.
+    pub fn render(&self, img: &DynamicImage, title: &str, credit: &str) -> Result<DynamicImage, Box<dyn Error>> {
+        let font = self.load_font()?;
+        let mut img = img.to_rgba8();
+
+        let size = (img.height() as f32 * self.size / 100.0).max(8.0);
+        let lines = [(title, size), (credit, size * CREDIT_SCALE)]
+            .into_iter()
+            .filter(|(text, _)| !text.trim().is_empty())
+            .map(|(text, size)| layout(&font, text.trim(), size))
+            .collect::<Vec<_>>();
+        if lines.is_empty() {
+            return Ok(DynamicImage::ImageRgba8(img));
+        }
+
+        let margin = size;
+        let padding = size * 0.4;
+        let width = lines.iter().map(|line| line.width).fold(0.0, f32::max);
+        let height = lines.iter().map(|line| line.height).sum::<f32>();
+
+        let left = match self.corner {
+            Corner::TopLeft | Corner::BottomLeft => margin,
+            Corner::TopRight | Corner::BottomRight => img.width() as f32 - margin - width,
+        };
+        let top = match self.corner {
+            Corner::TopLeft | Corner::TopRight => margin,
+            Corner::BottomLeft | Corner::BottomRight => img.height() as f32 - margin - height,
+        };
+        let area = (
+            left - padding,
+            top - padding,
+            width + padding * 2.0,
+            height + padding * 2.0,
+        );
+
+        let color = self.color.unwrap_or_else(|| {
+            let behind = average(&img, area);
+            if Color::WHITE.contrast(behind) >= Color::BLACK.contrast(behind) {
+                Color::WHITE
+            } else {
+                Color::BLACK
+            }
+        });
+        let shade = if color.luminance() > 0.5 {
+            Color::BLACK
+        } else {
+            Color::WHITE
+        };
+
+        match self.background {
+            Background::Backdrop => fill(&mut img, area, shade, BACKGROUND_ALPHA),
+            Background::Shadow => {
+                let offset = (size / 16.0).max(1.0);
+                let origin = (left + offset, top + offset);
+                draw_lines(
+                    &font,
+                    &mut img,
+                    &lines,
+                    origin,
+                    shade,
+                    BACKGROUND_ALPHA,
+                    align(self.corner),
+                );
+            }
+            Background::None => {}
+        }
+        draw_lines(&font, &mut img, &lines, (left, top), color, 1.0, align(self.corner));
+
+        Ok(DynamicImage::ImageRgba8(img))
+    }
+
+    /// Writes a copy of the image at `path` with its title and credit next to it, and returns its path. An existing
+    /// copy is kept.
+    pub fn caption(&self, path: impl AsRef<Path>, metadata: &Metadata) -> Result<PathBuf, Box<dyn Error>> {
+        let path = path.as_ref();
+        let dst = Self::path(path);
+        if dst.exists() {
+            return Ok(dst);
+        }
+        if !metadata.has_credits() {
+            return Err("no title or copyright to caption".into());
+        }
+
+        let img = util::open_image(path)?;
+        let img = self.render(
+            &img,
+            metadata.title.as_deref().unwrap_or_default(),
+            metadata.copyright.as_deref().unwrap_or_default(),
+        )?;
+        util::save_derived_image(&img, path, &dst)?;
+
+        Ok(dst)
+    }
+}
+
+/// Whether lines are aligned to the right.
+fn align(corner: Corner) -> bool {
+    matches!(corner, Corner::TopRight | Corner::BottomRight)
+}
+
+fn layout(font: &FontVec, text: &str, size: f32) -> Line {
+    let scale = PxScale::from(size);
+    let scaled = font.as_scaled(scale);
+
+    let mut glyphs = Vec::new();
+    let mut caret = 0.0;
+    let mut previous = None;
+    for c in text.chars().filter(|c| !c.is_control()) {
+        let id = font.glyph_id(c);
+        if let Some(previous) = previous {
+            caret += scaled.kern(previous, id);
+        }
+        glyphs.push(id.with_scale_and_position(scale, point(caret, 0.0)));
+        caret += scaled.h_advance(id);
+        previous = Some(id);
+    }
+
+    Line {
+        glyphs,
+        width: caret,
+        ascent: scaled.ascent(),
+        height: scaled.height() + scaled.line_gap(),
+    }
+}
+
+fn draw_lines(
+    font: &FontVec,
+    img: &mut RgbaImage,
+    lines: &[Line],
+    (left, top): (f32, f32),
+    color: Color,
+    alpha: f32,
+    right: bool,
+) {
+    let width = lines.iter().map(|line| line.width).fold(0.0, f32::max);
+    let mut y = top;
+
+    for line in lines {
+        let x = if right { left + width - line.width } else { left };
+        for glyph in &line.glyphs {
+            let mut glyph = glyph.clone();
+            glyph.position = point(x + glyph.position.x, y + line.ascent);
+
+            let Some(outlined) = font.outline_glyph(glyph) else {
+                continue;
+            };
+            let bounds = outlined.px_bounds();
+            outlined.draw(|gx, gy, coverage| {
+                let (px, py) = (bounds.min.x as i64 + gx as i64, bounds.min.y as i64 + gy as i64);
+                if px >= 0 && py >= 0 && (px as u32) < img.width() && (py as u32) < img.height() {
+                    blend(img.get_pixel_mut(px as u32, py as u32), color, coverage * alpha);
+                }
+            });
+        }
+        y += line.height;
+    }
+}
+
+/// Returns the pixels of an area that lie inside the image.
+fn clip(img: &RgbaImage, (left, top, width, height): (f32, f32, f32, f32)) -> (u32, u32, u32, u32) {
+    let x0 = (left.max(0.0) as u32).min(img.width());
+    let y0 = (top.max(0.0) as u32).min(img.height());
+    let x1 = ((left + width).max(0.0).ceil() as u32).min(img.width());
+    let y1 = ((top + height).max(0.0).ceil() as u32).min(img.height());
+    (x0, y0, x1, y1)
+}
+
+fn average(img: &RgbaImage, area: (f32, f32, f32, f32)) -> Color {
+    let (x0, y0, x1, y1) = clip(img, area);
+    let (mut sum, mut n) = ([0u64; 3], 0u64);
+
+    for y in y0..y1 {
+        for x in x0..x1 {
+            let pixel = img.get_pixel(x, y);
+            for (sum, channel) in sum.iter_mut().zip(pixel.0) {
+                *sum += channel as u64;
+            }
+            n += 1;
+        }
+    }
+
+    Color(sum.map(|sum| (sum / n.max(1)) as u8))
+}
+
+fn fill(img: &mut RgbaImage, area: (f32, f32, f32, f32), color: Color, alpha: f32) {
+    let (x0, y0, x1, y1) = clip(img, area);
+    for y in y0..y1 {
+        for x in x0..x1 {
+            blend(img.get_pixel_mut(x, y), color, alpha);
+        }
+    }
+}
+
+fn blend(pixel: &mut Rgba<u8>, color: Color, alpha: f32) {
+    let alpha = alpha.clamp(0.0, 1.0);
+    for (channel, value) in pixel.0.iter_mut().zip(color.0) {
+        *channel = (*channel as f32 * (1.0 - alpha) + value as f32 * alpha).round() as u8;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::Source;
+    use image::{Rgb, RgbImage};
+
+    #[test]
+    fn test_caption() {
+        let caption = Caption {
+            font: Some(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/DejaVuSans.ttf")),
+            ..Caption::default()
+        };
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("image.png");
+        RgbImage::from_pixel(400, 300, Rgb([20, 30, 60])).save(&path).unwrap();
+
+        let mut metadata = Metadata::new(Source::Bing, "image");
+        metadata.title = Some("Lighthouse at dawn".to_string());
+        metadata.copyright = Some("© Photographer".to_string());
+
+        let captioned = caption.caption(&path, &metadata).unwrap();
+        assert_eq!(captioned, dir.path().join("image_caption.png"));
+
+        // White text on the dark picture, in the bottom right corner only.
+        let img = util::open_image(&captioned).unwrap().to_rgb8();
+        let bright =
+            |x0: u32, y0: u32, x1: u32, y1: u32| (y0..y1).any(|y| (x0..x1).any(|x| img.get_pixel(x, y)[0] > 200));
+        assert!(bright(200, 200, 400, 300));
+        assert!(!bright(0, 0, 200, 150));
+    }
+}
@@ -0,0 +1,813 @@
+use rusqlite::{Connection, OptionalExtension, Row, params};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::derived::Kind;
+use crate::metadata::{Metadata, Source};
+use crate::palette::{self, Color, Palette};
+use crate::phash::PerceptualHash;
+use crate::resize::Focus;
+use crate::tone::Tone;
+
+/// Schema migrations, applied in order and tracked by `user_version`.
+const MIGRATIONS: &[&str] = &[
+    r#"
+CREATE TABLE images (
+    id INTEGER PRIMARY KEY,
+    source TEXT NOT NULL,
+    source_id TEXT NOT NULL,
+    title TEXT,
+    copyright TEXT,
+    date TEXT,
+    market TEXT,
+    width INTEGER,
+    height INTEGER,
+    file_size INTEGER,
+    wallpaper INTEGER,
+    dark INTEGER,
+    hash TEXT NOT NULL,
+    path TEXT NOT NULL UNIQUE,
+    first_seen INTEGER NOT NULL
+);
+
+CREATE INDEX images_hash ON images (hash);
+CREATE INDEX images_source_id ON images (source, source_id);
+"#,
+    r#"
+ALTER TABLE images ADD COLUMN phash TEXT;
+ALTER TABLE images ADD COLUMN group_id INTEGER REFERENCES images (id) ON DELETE SET NULL;
+
+CREATE INDEX images_group_id ON images (group_id);
+"#,
+    r#"
+ALTER TABLE images ADD COLUMN link TEXT;
+ALTER TABLE images ADD COLUMN url TEXT;
+"#,
+    r#"
+ALTER TABLE images ADD COLUMN favorite INTEGER NOT NULL DEFAULT 0;
+"#,
+    r#"
+ALTER TABLE images ADD COLUMN description TEXT;
+
+CREATE VIRTUAL TABLE images_fts USING fts5 (
+    title, copyright, description, market, date,
+    content = 'images', content_rowid = 'id', tokenize = 'unicode61 remove_diacritics 2'
+);
+
+INSERT INTO images_fts (images_fts) VALUES ('rebuild');
+
+CREATE TRIGGER images_fts_insert AFTER INSERT ON images BEGIN
+    INSERT INTO images_fts (rowid, title, copyright, description, market, date)
+    VALUES (new.id, new.title, new.copyright, new.description, new.market, new.date);
+END;
+
+CREATE TRIGGER images_fts_delete AFTER DELETE ON images BEGIN
+    INSERT INTO images_fts (images_fts, rowid, title, copyright, description, market, date)
+    VALUES ('delete', old.id, old.title, old.copyright, old.description, old.market, old.date);
+END;
+
+CREATE TRIGGER images_fts_update AFTER UPDATE OF title, copyright, description, market, date ON images BEGIN
+    INSERT INTO images_fts (images_fts, rowid, title, copyright, description, market, date)
+    VALUES ('delete', old.id, old.title, old.copyright, old.description, old.market, old.date);
+    INSERT INTO images_fts (rowid, title, copyright, description, market, date)
+    VALUES (new.id, new.title, new.copyright, new.description, new.market, new.date);
+END;
+"#,
+    r#"
+ALTER TABLE images ADD COLUMN hidden INTEGER NOT NULL DEFAULT 0;
+
+CREATE TABLE tags (
+    image_id INTEGER NOT NULL REFERENCES images (id) ON DELETE CASCADE,
+    tag TEXT NOT NULL COLLATE NOCASE,
+    PRIMARY KEY (image_id, tag)
+);
+
+CREATE INDEX tags_tag ON tags (tag);
+"#,
+    r#"
+ALTER TABLE images ADD COLUMN focus_x REAL;
+ALTER TABLE images ADD COLUMN focus_y REAL;
+"#,
+    r#"
+ALTER TABLE images ADD COLUMN palette TEXT;
+"#,
+    r#"
+ALTER TABLE images ADD COLUMN tone TEXT;
+
+UPDATE images SET tone = CASE dark WHEN 1 THEN 'dark' ELSE 'light' END WHERE dark IS NOT NULL;
+"#,
+    r#"
+ALTER TABLE images ADD COLUMN modified INTEGER;
+"#,
+    r#"
+CREATE TABLE pruned (
+    source TEXT NOT NULL,
+    source_id TEXT NOT NULL,
+    PRIMARY KEY (source, source_id)
+);
+"#,
+    r#"
+CREATE TABLE derived (
+    path TEXT PRIMARY KEY,
+    image_id INTEGER NOT NULL REFERENCES images (id) ON DELETE CASCADE,
+    kind TEXT NOT NULL
+);
+
+CREATE INDEX derived_image_id ON derived (image_id);
+"#,
+];
+
+const COLUMNS: &str = "id, source, source_id, title, copyright, date, market, width, height, \
+file_size, wallpaper, dark, hash, path, first_seen, phash, group_id, link, url, favorite, description, hidden, \
+focus_x, focus_y, palette, tone, modified, (SELECT group_concat(tag, char(31)) FROM tags WHERE tags.image_id = images.id) AS tags";
+
+/// Separates tags in the `tags` column of `COLUMNS`.
+const TAG_SEPARATOR: char = '\x1f';
+
+/// Date of an entry as `YYYYMMDD`: the date of the wallpaper, or else when it was first seen.
+const ENTRY_DATE: &str = "coalesce(replace(date, '-', ''), strftime('%Y%m%d', first_seen, 'unixepoch'))";
+
+/// Weights of the title, copyright, description, market and date columns when ranking search results.
+const RANK: &str = "bm25(images_fts, 10.0, 5.0, 2.0, 1.0, 1.0)";
+
+/// A full-text search, optionally limited to a date range.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Search {
+    /// Words that must all appear in the title, copyright, description, market or date. Words match as prefixes.
+    pub text: String,
+    /// Earliest date, such as `2024`, `2024-03` or `2024-03-15`.
+    pub since: Option<String>,
+    /// Latest date, such as `2024`, `2024-03` or `2024-03-15`.
+    pub until: Option<String>,
+    /// Only entries with this tag.
+    pub tag: Option<String>,
+    /// Only favorites.
+    pub favorite: bool,
+    /// Include hidden entries.
+    pub hidden: bool,
+    /// Only entries with a dominant color like this one, closest first unless searching text.
+    pub color: Option<Color>,
+    /// Only entries of this tone.
+    pub tone: Option<Tone>,
+}
+
+impl Search {
+    pub fn new(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Returns an FTS5 query matching every word as a prefix, or `None` if there are no words.
+    fn fts_query(&self) -> Option<String> {
+        let words = self
+            .text
+            .split_whitespace()
+            .map(|word| format!("\"{}\"*", word.replace('"', "\"\"")))
+            .collect::<Vec<_>>();
+
+        (!words.is_empty()).then(|| words.join(" "))
+    }
+}
+
+/// Returns a partial date as `YYYYMMDD`, padding missing digits with `pad`, so that `2024-03` is `20240300` as a
+/// lower bound and `20240399` as an upper bound.
+fn date_bound(date: &str, pad: char) -> Result<String, Box<dyn Error>> {
+    let digits = date.replace('-', "");
+
+    if !matches!(digits.len(), 4 | 6 | 8) || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return Err(format!("invalid date: {date}, expected YYYY, YYYY-MM or YYYY-MM-DD").into());
+    }
+
+    Ok(digits.chars().chain(std::iter::repeat(pad)).take(8).collect())
+}
+
+/// Returns a tag without surrounding whitespace, or an error if it is empty or contains the separator.
+fn valid_tag(tag: &str) -> Result<&str, Box<dyn Error>> {
+    let tag = tag.trim();
+    if tag.is_empty() || tag.contains(TAG_SEPARATOR) {
+        return Err(format!("invalid tag: {tag:?}").into());
+    }
+
+    Ok(tag)
+}
+
+/// A saved wallpaper.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    pub id: i64,
+    pub metadata: Metadata,
+    /// Hex encoded SHA-256 digest of the file.
+    pub hash: String,
+    pub path: PathBuf,
+    /// Seconds since the Unix epoch when the file was first added.
+    pub first_seen: u64,
+    /// Modification time of the file in nanoseconds since the Unix epoch when it was last hashed.
+    pub modified: Option<u64>,
+    pub phash: Option<PerceptualHash>,
+    /// Id of the entry that represents the logical wallpaper this entry is a copy of.
+    pub group_id: Option<i64>,
+    /// Favorites are never pruned.
+    pub favorite: bool,
+    /// Hidden entries stay in the catalog, so that syncing does not bring them back, but are not shown.
+    pub hidden: bool,
+    pub tags: Vec<String>,
+}
+
+impl Entry {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        let source: String = row.get("source")?;
+
+        let mut metadata = Metadata::new(
+            source.parse().map_err(|e: String| {
+                rusqlite::Error::FromSqlConversionFailure(1, rusqlite::types::Type::Text, e.into())
+            })?,
+            row.get::<_, String>("source_id")?,
+        );
+        metadata.title = row.get("title")?;
+        metadata.copyright = row.get("copyright")?;
+        metadata.date = row.get("date")?;
+        metadata.market = row.get("market")?;
+        metadata.width = row.get("width")?;
+        metadata.height = row.get("height")?;
+        metadata.file_size = row.get("file_size")?;
+        metadata.wallpaper = row.get("wallpaper")?;
+        metadata.dark = row.get("dark")?;
+        metadata.link = row.get("link")?;
+        metadata.url = row.get("url")?;
+        metadata.description = row.get("description")?;
+        metadata.focus = match (row.get("focus_x")?, row.get("focus_y")?) {
+            (Some(x), Some(y)) => Some(Focus { x, y }),
+            _ => None,
+        };
+        metadata.tone = row.get::<_, Option<String>>("tone")?.and_then(|tone| tone.parse().ok());
+        metadata.palette = row
+            .get::<_, Option<String>>("palette")?
+            .and_then(|palette| serde_json::from_str(&palette).ok());
+
+        Ok(Self {
+            id: row.get("id")?,
+            metadata,
+            hash: row.get("hash")?,
+            path: PathBuf::from(row.get::<_, String>("path")?),
+            first_seen: row.get("first_seen")?,
+            modified: row.get("modified")?,
+            phash: row
+                .get::<_, Option<String>>("phash")?
+                .and_then(|phash| phash.parse().ok()),
+            group_id: row.get("group_id")?,
+            favorite: row.get("favorite")?,
+            hidden: row.get("hidden")?,
+            tags: row
+                .get::<_, Option<String>>("tags")?
+                .map(|tags| {
+                    let mut tags = tags.split(TAG_SEPARATOR).map(String::from).collect::<Vec<_>>();
+                    tags.sort_by_key(|tag| tag.to_lowercase());
+                    tags
+                })
+                .unwrap_or_default(),
+        })
+    }
+}
+
+/// Returns seconds since the Unix epoch.
+pub fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+/// SQLite database of saved wallpapers.
+pub struct Catalog {
+    conn: Connection,
+}
+
+impl Catalog {
+    /// Opens or creates catalog.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        let conn = Connection::open(&path).map_err(|e| format!("failed to open {}: {}", path.as_ref().display(), e))?;
+        conn.pragma_update(None, "foreign_keys", true)?;
+
+        let catalog = Self { conn };
+        catalog.migrate()?;
+
+        Ok(catalog)
+    }
+
+    /// Opens an in-memory catalog.
+    pub fn open_in_memory() -> Result<Self, Box<dyn Error>> {
+        let catalog = Self {
+            conn: Connection::open_in_memory()?,
+        };
+        catalog.migrate()?;
+
+        Ok(catalog)
+    }
+
+    fn migrate(&self) -> Result<(), Box<dyn Error>> {
+        let version: usize = self.conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+
+        for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+            let tx = self.conn.unchecked_transaction()?;
+            tx.execute_batch(migration)
+                .map_err(|e| format!("failed to migrate catalog to version {}: {}", i + 1, e))?;
+            tx.pragma_update(None, "user_version", i + 1)?;
+            tx.commit()?;
+        }
+
+        Ok(())
+    }
+
+    /// Adds or updates the entry of a path. The first seen time of an existing entry is kept.
+    pub fn upsert(&self, metadata: &Metadata, hash: &str, path: impl AsRef<Path>) -> Result<Entry, Box<dyn Error>> {
+        let entry = self.conn.query_row(
+            &format!(
+                "INSERT INTO images (source, source_id, title, copyright, date, market, width, height, \
+                file_size, wallpaper, dark, hash, path, first_seen, link, url, description, focus_x, focus_y, tone, palette)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21)
+                ON CONFLICT (path) DO UPDATE SET
+                    source = excluded.source,
+                    source_id = excluded.source_id,
+                    title = coalesce(excluded.title, title),
+                    copyright = coalesce(excluded.copyright, copyright),
+                    date = coalesce(excluded.date, date),
+                    market = coalesce(excluded.market, market),
+                    width = excluded.width,
+                    height = excluded.height,
+                    file_size = excluded.file_size,
+                    wallpaper = coalesce(excluded.wallpaper, wallpaper),
+                    dark = coalesce(excluded.dark, dark),
+                    hash = excluded.hash,
+                    link = coalesce(excluded.link, link),
+                    url = coalesce(excluded.url, url),
+                    description = coalesce(excluded.description, description),
+                    focus_x = coalesce(excluded.focus_x, focus_x),
+                    focus_y = coalesce(excluded.focus_y, focus_y),
+                    tone = coalesce(excluded.tone, tone),
+                    palette = coalesce(excluded.palette, palette)
+                RETURNING {COLUMNS}"
+            ),
+            params![
+                metadata.source.to_string(),
+                metadata.id,
+                metadata.title,
+                metadata.copyright,
+                metadata.date,
+                metadata.market,
+                metadata.width,
+                metadata.height,
+                metadata.file_size,
+                metadata.wallpaper,
+                metadata.dark,
+                hash,
+                path.as_ref().to_string_lossy(),
+                now(),
+                metadata.link,
+                metadata.url,
+                metadata.description,
+                metadata.focus.map(|focus| focus.x),
+                metadata.focus.map(|focus| focus.y),
+                metadata.tone.map(|tone| tone.to_string()),
+                metadata.palette.as_ref().map(serde_json::to_string).transpose()?,
+            ],
+            Entry::from_row,
+        )?;
+
+        Ok(entry)
+    }
+
+    fn query(&self, sql: &str, params: impl rusqlite::Params) -> Result<Vec<Entry>, Box<dyn Error>> {
+        let mut stmt = self.conn.prepare(sql)?;
+        let entries = stmt
+            .query_map(params, Entry::from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(entries)
+    }
+
+    /// Returns all entries, most recently seen first.
+    pub fn list(&self) -> Result<Vec<Entry>, Box<dyn Error>> {
+        self.query(
+            &format!("SELECT {COLUMNS} FROM images ORDER BY first_seen DESC, id DESC"),
+            [],
+        )
+    }
+
+    pub fn get(&self, id: i64) -> Result<Option<Entry>, Box<dyn Error>> {
+        Ok(self
+            .conn
+            .query_row(
+                &format!("SELECT {COLUMNS} FROM images WHERE id = ?1"),
+                [id],
+                Entry::from_row,
+            )
+            .optional()?)
+    }
+
+    pub fn find_by_path(&self, path: impl AsRef<Path>) -> Result<Option<Entry>, Box<dyn Error>> {
+        Ok(self
+            .conn
+            .query_row(
+                &format!("SELECT {COLUMNS} FROM images WHERE path = ?1"),
+                [path.as_ref().to_string_lossy()],
+                Entry::from_row,
+            )
+            .optional()?)
+    }
+
+    pub fn find_by_hash(&self, hash: &str) -> Result<Vec<Entry>, Box<dyn Error>> {
+        self.query(
+            &format!("SELECT {COLUMNS} FROM images WHERE hash = ?1 ORDER BY id"),
+            [hash],
+        )
+    }
+
+    pub fn find_by_source(&self, source: Source, source_id: &str) -> Result<Vec<Entry>, Box<dyn Error>> {
+        self.query(
+            &format!("SELECT {COLUMNS} FROM images WHERE source = ?1 AND source_id = ?2 ORDER BY id"),
+            [source.to_string().as_str(), source_id],
+        )
+    }
+
+    /// Returns entries matching the search, best matches first. Without words, entries in the date range are
+    /// returned most recently seen first.
+    pub fn search(&self, search: &Search) -> Result<Vec<Entry>, Box<dyn Error>> {
+        let since = search.since.as_deref().map(|date| date_bound(date, '0')).transpose()?;
+        let until = search.until.as_deref().map(|date| date_bound(date, '9')).transpose()?;
+        let tone = search.tone.map(|tone| tone.to_string());
+
+        let range = format!(
+            "(?2 IS NULL OR {ENTRY_DATE} >= ?2) AND (?3 IS NULL OR {ENTRY_DATE} <= ?3)
+            AND (?4 IS NULL OR EXISTS (SELECT 1 FROM tags WHERE tags.image_id = images.id AND tags.tag = ?4))
+            AND (NOT ?5 OR favorite) AND (?6 OR NOT hidden) AND (?7 IS NULL OR tone = ?7)"
+        );
+
+        let mut entries = match search.fts_query() {
+            Some(query) => self.query(
+                &format!(
+                    "SELECT {COLUMNS} FROM images
+                    JOIN (SELECT rowid, {RANK} AS score FROM images_fts WHERE images_fts MATCH ?1) AS matches
+                        ON matches.rowid = images.id
+                    WHERE {range}
+                    ORDER BY score, first_seen DESC, id DESC"
+                ),
+                params![query, since, until, search.tag, search.favorite, search.hidden, tone],
+            ),
+            None => self.query(
+                &format!(
+                    "SELECT {COLUMNS} FROM images
+                    WHERE ?1 IS NULL AND {range}
+                    ORDER BY first_seen DESC, id DESC"
+                ),
+                params![
+                    None::<String>,
+                    since,
+                    until,
+                    search.tag,
+                    search.favorite,
+                    search.hidden,
+                    tone
+                ],
+            ),
+        }?;
+
+        if let Some(color) = search.color {
+            let distance = |entry: &Entry| {
+                entry
+                    .metadata
+                    .palette
+                    .as_ref()
+                    .map_or(f64::INFINITY, |p| p.distance(color))
+            };
+            entries.retain(|entry| distance(entry) <= palette::MATCH_DISTANCE);
+            if search.fts_query().is_none() {
+                entries.sort_by(|a, b| distance(a).total_cmp(&distance(b)));
+            }
+        }
+
+        Ok(entries)
+    }
+
+    pub fn remove(&self, id: i64) -> Result<(), Box<dyn Error>> {
+        self.conn.execute("DELETE FROM images WHERE id = ?1", [id])?;
+        Ok(())
+    }
+
+    /// Records a file kept with the file of an entry, such as the original of a converted image or a resized copy.
+    /// The file goes with the entry when it is moved or removed.
+    pub fn add_derived(&self, id: i64, path: impl AsRef<Path>, kind: Kind) -> Result<(), Box<dyn Error>> {
+        self.conn.execute(
+            "INSERT INTO derived (path, image_id, kind) VALUES (?1, ?2, ?3)
+            ON CONFLICT (path) DO UPDATE SET image_id = excluded.image_id, kind = excluded.kind",
+            params![path.as_ref().to_string_lossy(), id, kind.to_string()],
+        )?;
+        Ok(())
+    }
+
+    /// Returns the paths and kinds of files recorded with an entry.
+    pub fn derived(&self, id: i64) -> Result<Vec<(PathBuf, Kind)>, Box<dyn Error>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT path, kind FROM derived WHERE image_id = ?1 ORDER BY path")?;
+        let derived = stmt
+            .query_map([id], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .map(|(path, kind)| Ok((PathBuf::from(path), kind.parse()?)))
+            .collect::<Result<Vec<_>, String>>()?;
+
+        Ok(derived)
+    }
+
+    /// Returns whether the file is recorded with an entry.
+    pub fn is_derived(&self, path: impl AsRef<Path>) -> Result<bool, Box<dyn Error>> {
+        Ok(self
+            .conn
+            .query_row(
+                "SELECT 1 FROM derived WHERE path = ?1",
+                [path.as_ref().to_string_lossy()],
+                |_| Ok(()),
+            )
+            .optional()?
+            .is_some())
+    }
+
+    pub fn set_derived_path(&self, from: impl AsRef<Path>, to: impl AsRef<Path>) -> Result<(), Box<dyn Error>> {
+        self.conn.execute(
+            "UPDATE derived SET path = ?2 WHERE path = ?1",
+            params![from.as_ref().to_string_lossy(), to.as_ref().to_string_lossy()],
+        )?;
+        Ok(())
+    }
+
+    /// Records that the wallpaper was pruned, so that syncing does not bring it back.
+    pub fn add_pruned(&self, source: Source, source_id: &str) -> Result<(), Box<dyn Error>> {
+        self.conn.execute(
+            "INSERT OR IGNORE INTO pruned (source, source_id) VALUES (?1, ?2)",
+            params![source.to_string(), source_id],
+        )?;
+        Ok(())
+    }
+
+    pub fn is_pruned(&self, source: Source, source_id: &str) -> Result<bool, Box<dyn Error>> {
+        Ok(self
+            .conn
+            .query_row(
+                "SELECT 1 FROM pruned WHERE source = ?1 AND source_id = ?2",
+                params![source.to_string(), source_id],
+                |_| Ok(()),
+            )
+            .optional()?
+            .is_some())
+    }
+
+    pub fn set_path(&self, id: i64, path: impl AsRef<Path>) -> Result<(), Box<dyn Error>> {
+        self.conn.execute(
+            "UPDATE images SET path = ?2 WHERE id = ?1",
+            params![id, path.as_ref().to_string_lossy()],
+        )?;
+        Ok(())
+    }
+
+    pub fn set_favorite(&self, id: i64, favorite: bool) -> Result<(), Box<dyn Error>> {
+        self.conn
+            .execute("UPDATE images SET favorite = ?2 WHERE id = ?1", params![id, favorite])?;
+        Ok(())
+    }
+
+    pub fn set_hidden(&self, id: i64, hidden: bool) -> Result<(), Box<dyn Error>> {
+        self.conn
+            .execute("UPDATE images SET hidden = ?2 WHERE id = ?1", params![id, hidden])?;
+        Ok(())
+    }
+
+    /// Adds a tag to an entry. Tags are compared case-insensitively.
+    pub fn add_tag(&self, id: i64, tag: &str) -> Result<(), Box<dyn Error>> {
+        self.conn.execute(
+            "INSERT OR IGNORE INTO tags (image_id, tag) VALUES (?1, ?2)",
+            params![id, valid_tag(tag)?],
+        )?;
+        Ok(())
+    }
+
+    /// Replaces the tags of an entry. On failure the tags are left as they were.
+    pub fn set_tags(&self, id: i64, tags: &[String]) -> Result<(), Box<dyn Error>> {
+        let tx = self.conn.unchecked_transaction()?;
+
+        tx.execute("DELETE FROM tags WHERE image_id = ?1", [id])?;
+        for tag in tags {
+            tx.execute(
+                "INSERT OR IGNORE INTO tags (image_id, tag) VALUES (?1, ?2)",
+                params![id, valid_tag(tag)?],
+            )?;
+        }
+
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn remove_tag(&self, id: i64, tag: &str) -> Result<(), Box<dyn Error>> {
+        self.conn.execute(
+            "DELETE FROM tags WHERE image_id = ?1 AND tag = ?2",
+            params![id, tag.trim()],
+        )?;
+        Ok(())
+    }
+
+    /// Returns all tags with the number of entries having them, most used first.
+    pub fn tags(&self) -> Result<Vec<(String, usize)>, Box<dyn Error>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT tag, count(*) FROM tags GROUP BY tag ORDER BY count(*) DESC, tag")?;
+        let tags = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(tags)
+    }
+
+    pub fn set_modified(&self, id: i64, modified: Option<u64>) -> Result<(), Box<dyn Error>> {
+        self.conn
+            .execute("UPDATE images SET modified = ?2 WHERE id = ?1", params![id, modified])?;
+        Ok(())
+    }
+
+    pub fn set_phash(&self, id: i64, phash: PerceptualHash) -> Result<(), Box<dyn Error>> {
+        self.conn.execute(
+            "UPDATE images SET phash = ?2 WHERE id = ?1",
+            params![id, phash.to_string()],
+        )?;
+        Ok(())
+    }
+
+    pub fn set_palette(&self, id: i64, palette: &Palette) -> Result<(), Box<dyn Error>> {
+        self.conn.execute(
+            "UPDATE images SET palette = ?2 WHERE id = ?1",
+            params![id, serde_json::to_string(palette)?],
+        )?;
+        Ok(())
+    }
+
+    pub fn set_tone(&self, id: i64, tone: Tone) -> Result<(), Box<dyn Error>> {
+        self.conn.execute(
+            "UPDATE images SET tone = ?2 WHERE id = ?1",
+            params![id, tone.to_string()],
+        )?;
+        Ok(())
+    }
+
+    /// Links entries as copies of the same wallpaper, represented by `group_id`.
+    pub fn set_group(&self, ids: &[i64], group_id: i64) -> Result<(), Box<dyn Error>> {
+        let tx = self.conn.unchecked_transaction()?;
+        for id in ids {
+            tx.execute("UPDATE images SET group_id = ?2 WHERE id = ?1", [*id, group_id])?;
+        }
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    /// Returns entries linked to the group.
+    pub fn get_group(&self, group_id: i64) -> Result<Vec<Entry>, Box<dyn Error>> {
+        self.query(
+            &format!("SELECT {COLUMNS} FROM images WHERE group_id = ?1 ORDER BY id"),
+            [group_id],
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_upsert() {
+        let catalog = Catalog::open_in_memory().unwrap();
+
+        let mut metadata = Metadata::new(Source::Bing, "OHR.Lighthouse_EN-US1_UHD.jpg");
+        metadata.title = Some("Lighthouse".to_string());
+        metadata.market = Some("EN-US".to_string());
+
+        let entry = catalog.upsert(&metadata, "abc", "Lighthouse.jpg").unwrap();
+        assert_eq!(entry.metadata, metadata);
+
+        // Updating keeps known fields and the first seen time.
+        metadata.title = None;
+        metadata.width = Some(3840);
+        let updated = catalog.upsert(&metadata, "def", "Lighthouse.jpg").unwrap();
+        assert_eq!(updated.id, entry.id);
+        assert_eq!(updated.first_seen, entry.first_seen);
+        assert_eq!(updated.metadata.title.as_deref(), Some("Lighthouse"));
+        assert_eq!(updated.metadata.width, Some(3840));
+        assert_eq!(updated.hash, "def");
+
+        catalog
+            .upsert(&Metadata::new(Source::Spotlight, "asset"), "123", "asset.jpg")
+            .unwrap();
+
+        assert_eq!(catalog.list().unwrap().len(), 2);
+        assert_eq!(catalog.search(&Search::new("light")).unwrap(), vec![updated]);
+        assert!(catalog.search(&Search::new("100%")).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_search() {
+        let catalog = Catalog::open_in_memory().unwrap();
+
+        let mut metadata = Metadata::new(Source::Bing, "lighthouse");
+        metadata.title = Some("Lighthouse on the coast".to_string());
+        metadata.date = Some("20240415".to_string());
+        let lighthouse = catalog.upsert(&metadata, "a", "lighthouse.jpg").unwrap();
+
+        let mut metadata = Metadata::new(Source::Bing, "harbor");
+        metadata.title = Some("Harbor at dusk".to_string());
+        metadata.description = Some("An old lighthouse guards the harbor".to_string());
+        metadata.date = Some("20231102".to_string());
+        let harbor = catalog.upsert(&metadata, "b", "harbor.jpg").unwrap();
+
+        // Matches in the title rank above matches in the description.
+        assert_eq!(
+            catalog.search(&Search::new("lighthouse")).unwrap(),
+            vec![lighthouse.clone(), harbor.clone()]
+        );
+        assert_eq!(
+            catalog.search(&Search::new("light coast")).unwrap(),
+            vec![lighthouse.clone()]
+        );
+
+        let search = Search {
+            text: "lighthouse".to_string(),
+            since: Some("2024-03".to_string()),
+            until: Some("2024-05".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(catalog.search(&search).unwrap(), vec![lighthouse.clone()]);
+
+        let search = Search {
+            until: Some("2023".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(catalog.search(&search).unwrap(), vec![harbor.clone()]);
+
+        // Updated entries are indexed again.
+        let mut metadata = harbor.metadata.clone();
+        metadata.description = Some("Boats".to_string());
+        catalog.upsert(&metadata, "b", "harbor.jpg").unwrap();
+        assert_eq!(
+            catalog.search(&Search::new("lighthouse")).unwrap(),
+            vec![lighthouse.clone()]
+        );
+
+        catalog.add_tag(harbor.id, "Sea").unwrap();
+        catalog.add_tag(harbor.id, "sea").unwrap();
+        catalog.add_tag(harbor.id, "boats").unwrap();
+        catalog.set_hidden(lighthouse.id, true).unwrap();
+        catalog.set_favorite(harbor.id, true).unwrap();
+
+        let found = catalog.search(&Search::default()).unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].tags, vec!["boats", "Sea"]);
+        assert!(found[0].favorite);
+        let search = Search {
+            tag: Some("SEA".to_string()),
+            favorite: true,
+            ..Default::default()
+        };
+        assert_eq!(catalog.search(&search).unwrap(), found);
+        let search = Search {
+            hidden: true,
+            ..Default::default()
+        };
+        assert_eq!(catalog.search(&search).unwrap().len(), 2);
+        assert_eq!(
+            catalog.tags().unwrap(),
+            vec![("boats".to_string(), 1), ("Sea".to_string(), 1)]
+        );
+
+        catalog.remove_tag(harbor.id, " SEA ").unwrap();
+        catalog.remove_tag(harbor.id, "missing").unwrap();
+        assert_eq!(catalog.tags().unwrap(), vec![("boats".to_string(), 1)]);
+
+        let tags = ["coast".to_string(), "night".to_string()];
+        catalog.set_tags(harbor.id, &tags).unwrap();
+        assert!(
+            catalog
+                .set_tags(harbor.id, &["day".to_string(), " ".to_string()])
+                .is_err()
+        );
+        assert_eq!(catalog.search(&Search::default()).unwrap()[0].tags, tags);
+
+        catalog.remove(lighthouse.id).unwrap();
+        assert!(catalog.search(&Search::new("lighthouse")).unwrap().is_empty());
+        assert!(
+            catalog
+                .search(&Search {
+                    since: Some("March".to_string()),
+                    ..Default::default()
+                })
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,138 @@
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use crate::caption::Caption;
+use crate::convert::Convert;
+use crate::dedup::Dedup;
+use crate::effect::Effect;
+use crate::filter::Filter;
+use crate::layout::Layout;
+use crate::resize::Resize;
+use crate::retention::Retention;
+use crate::scheme::Export;
+use crate::sidecar;
+use crate::span::Span;
+use crate::template::Template;
+use crate::thumbnail::Thumbnail;
+
+/// Configuration loaded from a TOML file.
+///
+/// ```toml
+/// sidecars = ["json", "xmp"]
+/// embed = true
+/// template = "{date}_{title}_{market}.{ext}"
+/// layout = "year-month"
+///
+/// [retention]
+/// max_age_days = 90
+///
+/// [dedup]
+/// prefer = "resolution"
+///
+/// [filter]
+/// all = [
+///     { min_size = { width = 1920, height = 1080 } },
+///     { any = [{ market = "en-US" }, { market = "ja-JP" }] },
+/// ]
+/// ```
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Filter applied to wallpapers of all sources. Each source has its own default.
+    pub filter: Option<Filter>,
+    /// Keep only the best copy of visually identical images.
+    pub dedup: Option<Dedup>,
+    /// How saved files are named, arranged and processed.
+    #[serde(flatten)]
+    pub save: SaveOptions,
+    /// Monitors of `span`.
+    pub span: Option<Span>,
+    /// Files the color scheme of the newest Bing wallpaper is written to after saving Bing wallpapers.
+    pub schemes: Vec<Export>,
+    /// Rules of `library prune`.
+    pub retention: Option<Retention>,
+    /// Size and encoding of cached thumbnails.
+    pub thumbnail: Thumbnail,
+}
+
+/// How saved files are named, arranged and processed. Part of [`Config`], with the same keys.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SaveOptions {
+    /// Metadata files written next to saved images.
+    pub sidecars: Vec<sidecar::Format>,
+    /// Write credits into the EXIF, XMP and IPTC fields of saved JPEG files.
+    pub embed: bool,
+    /// Names of saved files. Bing images keep their id and Windows Spotlight images are named by content hash if unset.
+    pub template: Option<Template>,
+    /// Directories saved files are arranged in.
+    pub layout: Layout,
+    /// Format saved files are converted to. Files are kept as delivered if unset.
+    pub convert: Option<Convert>,
+    /// Screen geometries saved files are also resized and cropped to.
+    pub resize: Vec<Resize>,
+    /// Also save a copy of new files with their title and credit rendered onto them.
+    pub caption: Option<Caption>,
+    /// Lock screen variants, such as blurred or darkened copies, saved files are also written with.
+    pub effects: Vec<Effect>,
+}
+
+impl Config {
+    /// Loads configuration from a TOML file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        let content =
+            fs::read_to_string(&path).map_err(|e| format!("failed to read {}: {}", path.as_ref().display(), e))?;
+
+        toml::from_str(&content).map_err(|e| format!("failed to parse {}: {}", path.as_ref().display(), e).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter() {
+        let config: Config = toml::from_str(
+            r#"
+[filter]
+all = [
+    { min_size = { width = 1920, height = 1080 } },
+    { any = [{ market = "en-US" }, { orientation = "portrait" }] },
+]
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.filter,
+            Some(
+                "min-size=1920x1080 & (market=en-US | orientation=portrait)"
+                    .parse()
+                    .unwrap()
+            )
+        );
+    }
+
+    #[test]
+    fn test_save() {
+        let config: Config = toml::from_str(
+            r#"
+sidecars = ["json"]
+embed = true
+layout = "year-month"
+
+[dedup]
+prefer = "resolution"
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(config.save.sidecars, vec![sidecar::Format::Json]);
+        assert!(config.save.embed);
+        assert_eq!(config.save.layout, Layout::YearMonth);
+        assert!(config.dedup.is_some());
+    }
+}
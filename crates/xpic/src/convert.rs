@@ -0,0 +1,228 @@
+use image::codecs::avif::AvifEncoder;
+use image::codecs::png::PngEncoder;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::{template, util};
+
+/// AVIF encoder speed, from 1 (slowest, smallest) to 10.
+const AVIF_SPEED: u8 = 6;
+
+/// Format saved images are converted to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    Png,
+    Webp,
+    Avif,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "png" => Ok(Format::Png),
+            "webp" => Ok(Format::Webp),
+            "avif" => Ok(Format::Avif),
+            _ => Err(format!("unknown format: {s}")),
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Format::Png => write!(f, "png"),
+            Format::Webp => write!(f, "webp"),
+            Format::Avif => write!(f, "avif"),
+        }
+    }
+}
+
+impl Format {
+    fn image_format(self) -> image::ImageFormat {
+        match self {
+            Format::Png => image::ImageFormat::Png,
+            Format::Webp => image::ImageFormat::WebP,
+            Format::Avif => image::ImageFormat::Avif,
+        }
+    }
+}
+
+/// Conversion of saved images.
+///
+/// AVIF is only encoded, not decoded, so AVIF images are not analyzed and no resized, captioned or other copies are
+/// made from them.
+///
+/// ```toml
+/// [convert]
+/// format = "webp"
+/// quality = 75
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Convert {
+    pub format: Format,
+    /// Quality of lossy WebP and AVIF, from 1 to 100.
+    #[serde(default = "default_quality")]
+    pub quality: u8,
+    /// Encode WebP losslessly. PNG is always lossless and AVIF always lossy.
+    #[serde(default)]
+    pub lossless: bool,
+    /// Keep the original file next to the converted one.
+    #[serde(default)]
+    pub keep_original: bool,
+}
+
+fn default_quality() -> u8 {
+    80
+}
+
+impl Convert {
+    pub fn new(format: Format) -> Self {
+        Self {
+            format,
+            quality: default_quality(),
+            lossless: false,
+            keep_original: false,
+        }
+    }
+
+    fn encode(&self, src: &Path, dst: &Path) -> Result<(), Box<dyn Error>> {
+        let img = util::open_image(src)?;
+
+        match self.format {
+            Format::Png => img.write_with_encoder(PngEncoder::new(BufWriter::new(File::create(dst)?)))?,
+            Format::Webp => {
+                let rgba = img.to_rgba8();
+                let encoder = webp::Encoder::from_rgba(&rgba, rgba.width(), rgba.height());
+                let data = if self.lossless {
+                    encoder.encode_lossless()
+                } else {
+                    encoder.encode(self.quality.clamp(1, 100) as f32)
+                };
+                fs::write(dst, &*data)?;
+            }
+            Format::Avif => img.write_with_encoder(AvifEncoder::new_with_speed_quality(
+                BufWriter::new(File::create(dst)?),
+                AVIF_SPEED,
+                self.quality.clamp(1, 100),
+            ))?,
+        }
+
+        Ok(())
+    }
+
+    /// Converts an image to the format, next to the original, and returns the path of the converted image. The
+    /// original is removed unless it is kept. A file already at the converted path is never overwritten: ` (2)`,
+    /// ` (3)` and so on are appended to the name instead.
+    ///
+    /// EXIF data, ICC profiles and XMP packets are carried over to PNG and WebP, but not to AVIF. IPTC data is not
+    /// carried over. Images already in the format are left alone.
+    pub fn convert(&self, src: impl AsRef<Path>) -> Result<PathBuf, Box<dyn Error>> {
+        let src = src.as_ref();
+
+        if util::get_image_format(src)? == self.format.image_format() {
+            return Ok(src.to_path_buf());
+        }
+
+        let original = src.with_extension(self.format.to_string());
+        let mut dst = original.clone();
+        for n in 2.. {
+            if dst == src || !dst.exists() {
+                break;
+            }
+            dst = template::numbered(&original, n);
+        }
+
+        util::write_atomic(&dst, |tmp| {
+            self.encode(src, tmp)?;
+            util::copy_metadata(src, tmp)
+        })?;
+
+        // A file with a wrong extension is converted in place.
+        if !self.keep_original && dst != src {
+            fs::remove_file(src)?;
+        }
+
+        Ok(dst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embed;
+    use crate::metadata::{Metadata, Source};
+    use exif::{In, Tag};
+    use image::RgbImage;
+
+    #[test]
+    fn test_convert() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("image.jpg");
+        RgbImage::from_fn(32, 16, |x, y| image::Rgb([x as u8 * 8, y as u8 * 16, 0]))
+            .save(&src)
+            .unwrap();
+
+        let mut metadata = Metadata::new(Source::Bing, "image.jpg");
+        metadata.copyright = Some("© Photographer".to_string());
+        embed::embed(&src, &metadata).unwrap();
+
+        for (format, image_format) in [
+            (Format::Png, image::ImageFormat::Png),
+            (Format::Webp, image::ImageFormat::WebP),
+        ] {
+            let convert = Convert {
+                keep_original: true,
+                ..Convert::new(format)
+            };
+
+            let dst = convert.convert(&src).unwrap();
+            assert_eq!(dst, src.with_extension(format.to_string()));
+            assert_eq!(util::get_image_format(&dst).unwrap(), image_format);
+            assert_eq!(util::get_image_dimensions(&dst).unwrap(), (32, 16));
+
+            let exif = exif::Reader::new()
+                .read_from_container(&mut std::io::BufReader::new(File::open(&dst).unwrap()))
+                .unwrap();
+            let field = exif.get_field(Tag::Artist, In::PRIMARY).unwrap();
+            assert_eq!(field.display_value().to_string(), "\"Photographer\"");
+
+            // Converting again leaves the converted image alone.
+            assert_eq!(convert.convert(&dst).unwrap(), dst);
+        }
+
+        // An existing file is not overwritten, and the original is removed unless it is kept.
+        let png = src.with_extension("png");
+        let webp = fs::read(src.with_extension("webp")).unwrap();
+        let dst = Convert::new(Format::Webp).convert(&png).unwrap();
+        assert_eq!(dst, dir.path().join("image (2).webp"));
+        assert_eq!(util::get_image_format(&dst).unwrap(), image::ImageFormat::WebP);
+        assert_eq!(fs::read(src.with_extension("webp")).unwrap(), webp);
+        assert!(!png.exists());
+    }
+
+    #[test]
+    fn test_convert_avif() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("image.jpg");
+        RgbImage::from_fn(32, 16, |x, y| image::Rgb([x as u8 * 8, y as u8 * 16, 0]))
+            .save(&src)
+            .unwrap();
+
+        let dst = Convert::new(Format::Avif).convert(&src).unwrap();
+        assert_eq!(dst, src.with_extension("avif"));
+        assert!(!src.exists());
+
+        // AVIF cannot be decoded, so the format and dimensions are read back from the container.
+        assert_eq!(util::get_image_format(&dst).unwrap(), image::ImageFormat::Avif);
+        assert_eq!(util::get_image_dimensions(&dst).unwrap(), (32, 16));
+        assert_eq!(Convert::new(Format::Avif).convert(&dst).unwrap(), dst);
+    }
+}
@@ -0,0 +1,382 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+
+use crate::catalog::Entry;
+use crate::library::Library;
+use crate::phash::{self, PerceptualHash};
+use crate::util;
+
+/// Which copy of a picture to keep.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Preference {
+    /// Keep the copy with the most pixels.
+    #[default]
+    Resolution,
+    /// Keep the largest file.
+    FileSize,
+    /// Keep the most recently modified file.
+    Newest,
+}
+
+impl FromStr for Preference {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "resolution" => Ok(Preference::Resolution),
+            "file-size" => Ok(Preference::FileSize),
+            "newest" => Ok(Preference::Newest),
+            _ => Err(format!("unknown preference: {s}")),
+        }
+    }
+}
+
+impl fmt::Display for Preference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Preference::Resolution => write!(f, "resolution"),
+            Preference::FileSize => write!(f, "file-size"),
+            Preference::Newest => write!(f, "newest"),
+        }
+    }
+}
+
+/// Deduplication settings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Dedup {
+    pub prefer: Preference,
+    /// Maximum perceptual hash distance of the same picture.
+    pub threshold: u32,
+}
+
+impl Default for Dedup {
+    fn default() -> Self {
+        Self {
+            prefer: Preference::default(),
+            threshold: phash::DEFAULT_THRESHOLD,
+        }
+    }
+}
+
+/// An image considered for deduplication.
+#[derive(Debug, Clone)]
+pub struct Candidate {
+    pub path: PathBuf,
+    pub width: u32,
+    pub height: u32,
+    pub file_size: u64,
+    pub modified: SystemTime,
+    pub hash: PerceptualHash,
+}
+
+impl Candidate {
+    pub fn new(path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        let path = path.as_ref();
+        let (width, height) = util::get_image_dimensions(path)?;
+        let metadata = fs::metadata(path)?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            width,
+            height,
+            file_size: metadata.len(),
+            modified: metadata.modified()?,
+            hash: phash::hash_image(path)?,
+        })
+    }
+
+    /// Returns candidate of a catalog entry, using the first seen time as modification time.
+    pub fn from_entry(entry: &Entry) -> Option<Self> {
+        Some(Self {
+            path: entry.path.clone(),
+            width: entry.metadata.width?,
+            height: entry.metadata.height?,
+            file_size: entry.metadata.file_size?,
+            modified: SystemTime::UNIX_EPOCH + Duration::from_secs(entry.first_seen),
+            hash: entry.phash?,
+        })
+    }
+
+    fn key(&self, preference: Preference) -> (u128, u128) {
+        let area = self.width as u128 * self.height as u128;
+        let modified = self
+            .modified
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos());
+
+        match preference {
+            Preference::Resolution => (area, self.file_size as u128),
+            Preference::FileSize => (self.file_size as u128, area),
+            Preference::Newest => (modified, area),
+        }
+    }
+}
+
+/// A copy dropped in favour of a better one.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Dropped {
+    pub path: PathBuf,
+    pub kept: PathBuf,
+}
+
+/// Groups candidates whose hashes are within the threshold of each other, transitively.
+pub fn group(candidates: Vec<Candidate>, threshold: u32) -> Vec<Vec<Candidate>> {
+    let mut parents = (0..candidates.len()).collect::<Vec<_>>();
+
+    fn find(parents: &mut [usize], i: usize) -> usize {
+        let mut root = i;
+        while parents[root] != root {
+            root = parents[root];
+        }
+        parents[i] = root;
+        root
+    }
+
+    for i in 0..candidates.len() {
+        for j in i + 1..candidates.len() {
+            if candidates[i].hash.distance(candidates[j].hash) <= threshold {
+                let (a, b) = (find(&mut parents, i), find(&mut parents, j));
+                parents[b] = a;
+            }
+        }
+    }
+
+    let mut groups: Vec<Vec<Candidate>> = Vec::new();
+    let mut indices = Vec::new();
+
+    for (i, candidate) in candidates.into_iter().enumerate() {
+        let root = find(&mut parents, i);
+        match indices.iter().position(|&r| r == root) {
+            Some(index) => groups[index].push(candidate),
+            None => {
+                indices.push(root);
+                groups.push(vec![candidate]);
+            }
+        }
+    }
+
+    groups
+}
+
+/// Keeps the best candidate of each group and returns what was kept and dropped.
+pub fn dedup(candidates: Vec<Candidate>, dedup: Dedup) -> (Vec<Candidate>, Vec<Dropped>) {
+    let mut kept = Vec::new();
+    let mut dropped = Vec::new();
+
+    for group in group(candidates, dedup.threshold) {
+        if let Some((best, others)) = best_of(group, dedup.prefer) {
+            dropped.extend(others);
+            kept.push(best);
+        }
+    }
+
+    (kept, dropped)
+}
+
+fn best_of(mut group: Vec<Candidate>, preference: Preference) -> Option<(Candidate, Vec<Dropped>)> {
+    group.sort_by_key(|candidate| std::cmp::Reverse(candidate.key(preference)));
+
+    let mut group = group.into_iter();
+    let best = group.next()?;
+
+    let dropped = group
+        .map(|candidate| Dropped {
+            path: candidate.path,
+            kept: best.path.clone(),
+        })
+        .collect();
+
+    Some((best, dropped))
+}
+
+/// Links near-identical wallpapers across the whole library, regardless of source, as one logical wallpaper
+/// represented by its best copy, and returns the other copies. The other copies are deleted if `remove` is set.
+///
+/// Entries saved before perceptual hashes were recorded are hashed first.
+pub fn dedup_library(library: &Library, dedup: Dedup, remove: bool) -> Result<Vec<Dropped>, Box<dyn Error>> {
+    let mut entries = HashMap::new();
+    let mut candidates = Vec::new();
+
+    for mut entry in library.list()? {
+        // An entry whose file is gone must not be kept in place of a copy that is still there.
+        if !entry.path.exists() {
+            continue;
+        }
+
+        if entry.phash.is_none() {
+            match phash::hash_image(&entry.path) {
+                Ok(phash) => {
+                    library.catalog().set_phash(entry.id, phash)?;
+                    entry.phash = Some(phash);
+                }
+                Err(err) => {
+                    eprintln!("failed to hash image {}: {}", entry.path.display(), err);
+                    continue;
+                }
+            }
+        }
+
+        if let Some(candidate) = Candidate::from_entry(&entry) {
+            candidates.push(candidate);
+            entries.insert(entry.path.clone(), entry);
+        }
+    }
+
+    let mut dropped = Vec::new();
+
+    for group in group(candidates, dedup.threshold) {
+        if group.len() < 2 {
+            continue;
+        }
+
+        let ids = group
+            .iter()
+            .map(|candidate| entries[&candidate.path].id)
+            .collect::<Vec<_>>();
+
+        // Hiding a wallpaper hides all its copies, including ones saved later.
+        let hidden = group.iter().any(|candidate| entries[&candidate.path].hidden);
+
+        let Some((best, others)) = best_of(group, dedup.prefer) else {
+            continue;
+        };
+        library.catalog().set_group(&ids, entries[&best.path].id)?;
+        if hidden {
+            for id in &ids {
+                library.catalog().set_hidden(*id, true)?;
+            }
+        }
+
+        for other in others {
+            if remove {
+                library.remove(&entries[&other.path])?;
+            }
+            dropped.push(other);
+        }
+    }
+
+    Ok(dropped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::{Metadata, Source};
+    use image::{DynamicImage, RgbImage};
+
+    fn candidate(path: &str, img: &DynamicImage, file_size: u64) -> Candidate {
+        Candidate {
+            path: PathBuf::from(path),
+            width: img.width(),
+            height: img.height(),
+            file_size,
+            modified: SystemTime::UNIX_EPOCH,
+            hash: PerceptualHash::of(img),
+        }
+    }
+
+    #[test]
+    fn test_dedup() {
+        let gradient = RgbImage::from_fn(384, 216, |x, y| {
+            image::Rgb([(x * 255 / 384) as u8, (y * 255 / 216) as u8, ((x + y) % 256) as u8])
+        });
+        let large = DynamicImage::ImageRgb8(gradient);
+        let small = large.resize_exact(192, 108, image::imageops::FilterType::Lanczos3);
+        let other = DynamicImage::ImageRgb8(RgbImage::from_fn(384, 216, |x, y| {
+            image::Rgb([(255 - x * 255 / 384) as u8, (y * 255 / 216) as u8, 0])
+        }));
+
+        let candidates = vec![
+            candidate("small", &small, 300),
+            candidate("other", &other, 100),
+            candidate("large", &large, 200),
+        ];
+
+        let (kept, dropped) = dedup(candidates.clone(), Dedup::default());
+        let mut kept = kept.into_iter().map(|c| c.path).collect::<Vec<_>>();
+        kept.sort();
+        assert_eq!(kept, vec![PathBuf::from("large"), PathBuf::from("other")]);
+        assert_eq!(
+            dropped,
+            vec![Dropped {
+                path: PathBuf::from("small"),
+                kept: PathBuf::from("large"),
+            }]
+        );
+
+        let dedup_by_size = Dedup {
+            prefer: Preference::FileSize,
+            ..Dedup::default()
+        };
+        let (_, dropped) = dedup(candidates, dedup_by_size);
+        assert_eq!(dropped[0].path, PathBuf::from("large"));
+    }
+
+    #[test]
+    fn test_dedup_library() {
+        let dir = tempfile::tempdir().unwrap();
+        let library = Library::open(dir.path()).unwrap();
+
+        let large = DynamicImage::ImageRgb8(RgbImage::from_fn(384, 216, |x, y| {
+            image::Rgb([(x * 255 / 384) as u8, (y * 255 / 216) as u8, ((x + y) % 256) as u8])
+        }));
+        let small = large.resize_exact(192, 108, image::imageops::FilterType::Lanczos3);
+        large.save(dir.path().join("large.png")).unwrap();
+        small.save(dir.path().join("small.png")).unwrap();
+
+        let large = library
+            .add(Metadata::new(Source::Bing, "large"), dir.path().join("large.png"))
+            .unwrap();
+        let small = library
+            .add(Metadata::new(Source::Spotlight, "small"), dir.path().join("small.png"))
+            .unwrap();
+
+        let dropped = dedup_library(&library, Dedup::default(), false).unwrap();
+        assert_eq!(
+            dropped,
+            vec![Dropped {
+                path: small.path.clone(),
+                kept: large.path.clone(),
+            }]
+        );
+        assert_eq!(library.catalog().get_group(large.id).unwrap().len(), 2);
+
+        dedup_library(&library, Dedup::default(), true).unwrap();
+        assert!(!small.path.exists());
+        assert_eq!(library.list().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_dedup_library_missing() {
+        let dir = tempfile::tempdir().unwrap();
+        let library = Library::open(dir.path()).unwrap();
+
+        let large = DynamicImage::ImageRgb8(RgbImage::from_fn(384, 216, |x, y| {
+            image::Rgb([(x * 255 / 384) as u8, (y * 255 / 216) as u8, ((x + y) % 256) as u8])
+        }));
+        let small = large.resize_exact(192, 108, image::imageops::FilterType::Lanczos3);
+        large.save(dir.path().join("large.png")).unwrap();
+        small.save(dir.path().join("small.png")).unwrap();
+
+        let large = library
+            .add(Metadata::new(Source::Bing, "large"), dir.path().join("large.png"))
+            .unwrap();
+        let small = library
+            .add(Metadata::new(Source::Spotlight, "small"), dir.path().join("small.png"))
+            .unwrap();
+
+        // The better copy is gone, so the one still there is not a duplicate of it.
+        fs::remove_file(&large.path).unwrap();
+        assert!(dedup_library(&library, Dedup::default(), true).unwrap().is_empty());
+        assert!(small.path.exists());
+        assert_eq!(library.list().unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,54 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// What a file recorded with an entry is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Kind {
+    /// The original of a converted image.
+    Original,
+    /// A copy resized to fit a screen.
+    Resize,
+    /// A copy with the credits rendered on it.
+    Caption,
+    /// A copy with effects applied, e.g. for the lock screen.
+    Effect,
+}
+
+impl FromStr for Kind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "original" => Ok(Kind::Original),
+            "resize" => Ok(Kind::Resize),
+            "caption" => Ok(Kind::Caption),
+            "effect" => Ok(Kind::Effect),
+            _ => Err(format!("unknown kind of derived file: {s}")),
+        }
+    }
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Kind::Original => write!(f, "original"),
+            Kind::Resize => write!(f, "resize"),
+            Kind::Caption => write!(f, "caption"),
+            Kind::Effect => write!(f, "effect"),
+        }
+    }
+}
+
+/// Returns where a copy made from `path` is saved: next to it, with the suffix appended to the name, e.g.
+/// `image_2560x1440.jpg` for the suffix `2560x1440`.
+pub fn path(path: impl AsRef<Path>, suffix: &str) -> PathBuf {
+    let path = path.as_ref();
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = format!("{stem}_{suffix}");
+
+    match path.extension() {
+        Some(ext) => path.with_file_name(format!("{name}.{}", ext.to_string_lossy())),
+        None => path.with_file_name(name),
+    }
+}
@@ -0,0 +1,199 @@
+use image::imageops::FilterType;
+use image::{DynamicImage, RgbImage};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::derived;
+use crate::util;
+
+/// Blur radius, in pixels, above which images are blurred at a reduced size.
+const FULL_SIZE_BLUR: f32 = 4.0;
+
+/// A variant of a wallpaper for lock screens and login greeters, such as a blurred, darkened or gray copy.
+///
+/// Variants are named after their effects, e.g. `blur2-dim40` for a blurred and darkened copy.
+///
+/// ```toml
+/// [[effects]]
+/// # Blur radius in percent of the image height.
+/// blur = 2.0
+/// # Darken by 40%.
+/// dim = 40.0
+///
+/// [[effects]]
+/// # Remove all color.
+/// desaturate = 100.0
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Effect {
+    /// Radius of the Gaussian blur in percent of the image height.
+    pub blur: f32,
+    /// How much darker, from 0 to 100 percent.
+    pub dim: f32,
+    /// How much color is removed, from 0 to 100 percent.
+    pub desaturate: f32,
+}
+
+impl FromStr for Effect {
+    type Err = String;
+
+    /// Parses effects with their strength joined by `-`, e.g. `blur2-dim40` or `desaturate100`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid effect: {s}, expected e.g. blur2-dim40-desaturate100");
+        let mut effect = Effect::default();
+
+        for part in s.split('-') {
+            let split = part
+                .find(|c: char| c.is_ascii_digit() || c == '.')
+                .ok_or_else(invalid)?;
+            let (name, strength) = part.split_at(split);
+            let strength = strength.parse::<f32>().map_err(|_| invalid())?;
+
+            match name {
+                "blur" => effect.blur = strength,
+                "dim" => effect.dim = strength,
+                "desaturate" => effect.desaturate = strength,
+                _ => return Err(invalid()),
+            }
+        }
+
+        effect.validate()?;
+        Ok(effect)
+    }
+}
+
+impl fmt::Display for Effect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parts = [("blur", self.blur), ("dim", self.dim), ("desaturate", self.desaturate)]
+            .into_iter()
+            .filter(|(_, strength)| *strength > 0.0)
+            .map(|(name, strength)| format!("{name}{strength}"))
+            .collect::<Vec<_>>();
+
+        write!(f, "{}", parts.join("-"))
+    }
+}
+
+impl Effect {
+    /// Checks that there is an effect and strengths are in range.
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.blur.is_finite() && self.blur >= 0.0) {
+            return Err(format!("invalid blur: {}", self.blur));
+        }
+        for (name, strength) in [("dim", self.dim), ("desaturate", self.desaturate)] {
+            if !(0.0..=100.0).contains(&strength) {
+                return Err(format!("invalid {name}: {strength}, expected 0 to 100"));
+            }
+        }
+        if self.blur == 0.0 && self.dim == 0.0 && self.desaturate == 0.0 {
+            return Err("no effect".to_string());
+        }
+
+        Ok(())
+    }
+
+    /// Returns a copy of an image with the effects applied.
+    pub fn apply(&self, img: &DynamicImage) -> DynamicImage {
+        let (width, height) = (img.width(), img.height());
+        let radius = height as f32 * self.blur / 100.0;
+
+        let mut img = if radius <= 0.0 {
+            img.to_rgb8()
+        } else if radius <= FULL_SIZE_BLUR {
+            img.blur(radius).to_rgb8()
+        } else {
+            // A strong blur leaves no detail, so blurring a smaller copy and scaling it back up looks the same and is
+            // much faster.
+            let scale = FULL_SIZE_BLUR / radius;
+            let small = img.resize_exact(
+                ((width as f32 * scale).round() as u32).max(1),
+                ((height as f32 * scale).round() as u32).max(1),
+                FilterType::Triangle,
+            );
+            small
+                .blur(FULL_SIZE_BLUR)
+                .resize_exact(width, height, FilterType::CatmullRom)
+                .to_rgb8()
+        };
+
+        let (dim, desaturate) = (1.0 - self.dim / 100.0, self.desaturate / 100.0);
+        for pixel in RgbImage::pixels_mut(&mut img) {
+            let [r, g, b] = pixel.0.map(f32::from);
+            let luma = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+            pixel.0 = [r, g, b].map(|c| ((c + (luma - c) * desaturate) * dim).round().clamp(0.0, 255.0) as u8);
+        }
+
+        DynamicImage::ImageRgb8(img)
+    }
+
+    /// Returns where the variant of `path` is saved: next to it, with the effects appended to the name, e.g.
+    /// `image_blur2-dim40.jpg`.
+    pub fn path(&self, path: impl AsRef<Path>) -> PathBuf {
+        derived::path(path, &self.to_string())
+    }
+
+    /// Writes the variant of `path` next to it in the same format, keeping EXIF data and the ICC profile, and returns
+    /// its path. An existing variant is kept.
+    pub fn derive(&self, path: impl AsRef<Path>) -> Result<PathBuf, Box<dyn Error>> {
+        self.validate()?;
+
+        let path = path.as_ref();
+        let dst = self.path(path);
+        if dst.exists() {
+            return Ok(dst);
+        }
+
+        let img = self.apply(&util::open_image(path)?);
+        util::save_derived_image(&img, path, &dst)?;
+
+        Ok(dst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgb;
+
+    #[test]
+    fn test_effect() {
+        let effect: Effect = "blur2-dim50-desaturate100".parse().unwrap();
+        assert_eq!(
+            effect,
+            Effect {
+                blur: 2.0,
+                dim: 50.0,
+                desaturate: 100.0
+            }
+        );
+        assert_eq!(effect.to_string(), "blur2-dim50-desaturate100");
+        assert_eq!("dim12.5".parse::<Effect>().unwrap().to_string(), "dim12.5");
+        assert!("dim120".parse::<Effect>().is_err());
+        assert!("dim0".parse::<Effect>().is_err());
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("image.png");
+        RgbImage::from_fn(
+            200,
+            100,
+            |x, _| if x < 100 { Rgb([200, 0, 0]) } else { Rgb([0, 0, 200]) },
+        )
+        .save(&path)
+        .unwrap();
+
+        let variant = effect.derive(&path).unwrap();
+        assert_eq!(variant, dir.path().join("image_blur2-dim50-desaturate100.png"));
+
+        // Gray, half as bright, and the edge between the halves is soft.
+        let img = util::open_image(&variant).unwrap().to_rgb8();
+        let left = img.get_pixel(10, 50);
+        assert!(left[0] == left[1] && left[1] == left[2]);
+        assert!((left[0] as i32 - 21).abs() <= 2);
+        let edge = img.get_pixel(100, 50)[0];
+        assert!(edge > left[0].min(img.get_pixel(190, 50)[0]) && edge < left[0].max(img.get_pixel(190, 50)[0]));
+    }
+}
@@ -0,0 +1,332 @@
+use exif::{Field, In, Tag, Value};
+use img_parts::Bytes;
+use img_parts::jpeg::{Jpeg, JpegSegment, markers};
+use std::error::Error;
+use std::fs;
+use std::io::Cursor;
+use std::path::Path;
+
+use crate::metadata::{Metadata, iso_date};
+use crate::sidecar;
+use crate::util;
+
+const EXIF_PREFIX: &[u8] = b"Exif\0\0";
+const XMP_PREFIX: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+const PHOTOSHOP_PREFIX: &[u8] = b"Photoshop 3.0\0";
+
+/// EXIF tags written by `embed`. Other tags already in the file are kept.
+const EXIF_TAGS: [Tag; 4] = [
+    Tag::ImageDescription,
+    Tag::Artist,
+    Tag::Copyright,
+    Tag::DateTimeOriginal,
+];
+
+fn ascii(tag: Tag, value: &str) -> Field {
+    Field {
+        tag,
+        ifd_num: In::PRIMARY,
+        value: Value::Ascii(vec![value.as_bytes().to_vec()]),
+    }
+}
+
+/// Returns the JPEG thumbnail stored in EXIF data.
+fn thumbnail(exif: &exif::Exif) -> Option<&[u8]> {
+    let offset = exif
+        .get_field(Tag::JPEGInterchangeFormat, In::THUMBNAIL)?
+        .value
+        .get_uint(0)? as usize;
+    let length = exif
+        .get_field(Tag::JPEGInterchangeFormatLength, In::THUMBNAIL)?
+        .value
+        .get_uint(0)? as usize;
+
+    exif.buf().get(offset..offset.checked_add(length)?)
+}
+
+/// Returns EXIF data with the credits of the metadata, keeping other fields and the thumbnail from `existing`, or
+/// `None` if `existing` has to be kept as is.
+///
+/// A maker note holds offsets into the EXIF data in a format private to the camera maker, which moving it around
+/// would break, so EXIF data with a maker note is never rewritten.
+fn exif(metadata: &Metadata, existing: Option<&[u8]>) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+    let existing = existing.and_then(|data| exif::Reader::new().read_raw(data.to_vec()).ok());
+
+    if existing
+        .as_ref()
+        .is_some_and(|exif| exif.get_field(Tag::MakerNote, In::PRIMARY).is_some())
+    {
+        return Ok(None);
+    }
+
+    let thumbnail = existing.as_ref().and_then(thumbnail);
+
+    let mut fields = existing
+        .iter()
+        .flat_map(|exif| exif.fields())
+        .filter(|field| match field.ifd_num {
+            In::PRIMARY => !EXIF_TAGS.contains(&field.tag),
+            // The thumbnail IFD describes the thumbnail, so it is dropped along with a thumbnail that is not JPEG.
+            In::THUMBNAIL => thumbnail.is_some(),
+            _ => false,
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+
+    if let Some(title) = &metadata.title {
+        fields.push(ascii(Tag::ImageDescription, title));
+    }
+    if let Some(holder) = metadata.copyright_holder() {
+        fields.push(ascii(Tag::Artist, holder));
+    }
+    if let Some(copyright) = &metadata.copyright {
+        fields.push(ascii(Tag::Copyright, copyright));
+    }
+    if let Some(date) = &metadata.date {
+        let date = iso_date(date).replace('-', ":");
+        fields.push(ascii(Tag::DateTimeOriginal, &format!("{date} 00:00:00")));
+    }
+
+    let mut writer = exif::experimental::Writer::new();
+    fields.iter().for_each(|field| writer.push_field(field));
+    if let Some(thumbnail) = thumbnail {
+        writer.set_jpeg(thumbnail, In::THUMBNAIL);
+    }
+
+    let mut buf = Cursor::new(Vec::new());
+    writer.write(&mut buf, existing.as_ref().is_some_and(|exif| exif.little_endian()))?;
+
+    Ok(Some(buf.into_inner()))
+}
+
+/// Returns an IPTC-IIM record wrapped in a Photoshop image resource block, as stored in APP13.
+fn iptc(metadata: &Metadata) -> Vec<u8> {
+    let mut records = Vec::new();
+
+    let mut push = |record: u8, dataset: u8, value: &[u8]| {
+        records.extend([0x1c, record, dataset]);
+        records.extend((value.len() as u16).to_be_bytes());
+        records.extend(value);
+    };
+
+    // Coded character set: UTF-8.
+    push(1, 90, b"\x1b%G");
+    push(2, 0, &[0, 4]);
+    if let Some(title) = &metadata.title {
+        push(2, 5, title.as_bytes());
+        push(2, 120, title.as_bytes());
+    }
+    if let Some(holder) = metadata.copyright_holder() {
+        push(2, 80, holder.as_bytes());
+    }
+    if let Some(copyright) = &metadata.copyright {
+        push(2, 116, copyright.as_bytes());
+    }
+    if let Some(date) = &metadata.date {
+        push(2, 55, iso_date(date).replace('-', "").as_bytes());
+    }
+    if let Some(url) = &metadata.url {
+        push(2, 115, url.as_bytes());
+    }
+
+    let mut data = PHOTOSHOP_PREFIX.to_vec();
+    data.extend(b"8BIM");
+    data.extend(0x0404u16.to_be_bytes());
+    // Empty resource name, padded to an even length.
+    data.extend([0, 0]);
+    data.extend((records.len() as u32).to_be_bytes());
+    data.extend(&records);
+    if records.len() % 2 == 1 {
+        data.push(0);
+    }
+
+    data
+}
+
+fn prefixed(prefix: &[u8], data: &[u8]) -> Bytes {
+    Bytes::from([prefix, data].concat())
+}
+
+/// Writes the title, copyright holder, description, date and source URL of the metadata into the EXIF, XMP and IPTC
+/// fields of a JPEG file, and returns whether the file was changed.
+///
+/// Only metadata segments are replaced, so the pixels are not recompressed. Files that are not JPEG, and images
+/// without title or copyright, are left alone.
+pub fn embed(path: impl AsRef<Path>, metadata: &Metadata) -> Result<bool, Box<dyn Error>> {
+    let path = path.as_ref();
+
+    if !metadata.has_credits() || util::get_image_format(path)? != image::ImageFormat::Jpeg {
+        return Ok(false);
+    }
+
+    let mut jpeg =
+        Jpeg::from_bytes(fs::read(path)?.into()).map_err(|e| format!("failed to parse {}: {}", path.display(), e))?;
+
+    let existing = jpeg.segments().iter().find_map(|segment| {
+        (segment.marker() == markers::APP1 && segment.contents().starts_with(EXIF_PREFIX))
+            .then(|| segment.contents().slice(EXIF_PREFIX.len()..))
+    });
+    let exif = exif(metadata, existing.as_deref())?;
+
+    let segments = jpeg.segments_mut();
+    segments.retain(|segment| {
+        let contents = segment.contents();
+        !(segment.marker() == markers::APP1 && contents.starts_with(EXIF_PREFIX) && exif.is_some()
+            || segment.marker() == markers::APP1 && contents.starts_with(XMP_PREFIX)
+            || segment.marker() == markers::APP13 && contents.starts_with(PHOTOSHOP_PREFIX))
+    });
+
+    // Metadata goes after the leading application segments, such as JFIF.
+    let index = segments
+        .iter()
+        .position(|segment| !(markers::APP0..=markers::APP15).contains(&segment.marker()))
+        .unwrap_or(segments.len());
+
+    let exif = exif.map(|exif| JpegSegment::new_with_contents(markers::APP1, prefixed(EXIF_PREFIX, &exif)));
+    segments.splice(
+        index..index,
+        exif.into_iter().chain([
+            JpegSegment::new_with_contents(
+                markers::APP1,
+                prefixed(XMP_PREFIX, sidecar::to_xmp(metadata).as_bytes()),
+            ),
+            JpegSegment::new_with_contents(markers::APP13, Bytes::from(iptc(metadata))),
+        ]),
+    );
+
+    util::write_atomic(path, |tmp| {
+        jpeg.encoder()
+            .write_to(fs::File::create(tmp)?)
+            .map_err(|e| format!("failed to write {}: {}", tmp.display(), e))?;
+        Ok(())
+    })?;
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::Source;
+    use image::RgbImage;
+
+    #[test]
+    fn test_embed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("image.jpg");
+        RgbImage::from_fn(32, 16, |x, y| image::Rgb([x as u8 * 8, y as u8 * 16, 0]))
+            .save(&path)
+            .unwrap();
+
+        let mut metadata = Metadata::new(Source::Bing, "image.jpg");
+        assert!(!embed(&path, &metadata).unwrap());
+
+        metadata.title = Some("Lighthouse".to_string());
+        metadata.copyright = Some("© Photographer".to_string());
+        metadata.date = Some("20240131".to_string());
+
+        let pixels = util::open_image(&path).unwrap();
+        assert!(embed(&path, &metadata).unwrap());
+        // Embedding again replaces the segments instead of adding more.
+        assert!(embed(&path, &metadata).unwrap());
+        assert_eq!(util::open_image(&path).unwrap(), pixels);
+
+        let exif = exif::Reader::new()
+            .read_from_container(&mut std::io::BufReader::new(fs::File::open(&path).unwrap()))
+            .unwrap();
+        let field = exif.get_field(Tag::Artist, In::PRIMARY).unwrap();
+        assert_eq!(field.display_value().to_string(), "\"Photographer\"");
+        let field = exif.get_field(Tag::DateTimeOriginal, In::PRIMARY).unwrap();
+        assert_eq!(field.display_value().to_string(), "2024-01-31 00:00:00");
+
+        let jpeg = Jpeg::from_bytes(fs::read(&path).unwrap().into()).unwrap();
+        assert_eq!(
+            jpeg.segments()
+                .iter()
+                .filter(|segment| segment.contents().starts_with(XMP_PREFIX))
+                .count(),
+            1
+        );
+    }
+
+    /// Writes a JPEG file with the fields and a JPEG thumbnail in its EXIF data.
+    fn jpeg_with_exif(path: &Path, fields: &[Field], thumbnail: &[u8]) {
+        RgbImage::new(32, 16).save(path).unwrap();
+
+        let mut writer = exif::experimental::Writer::new();
+        fields.iter().for_each(|field| writer.push_field(field));
+        writer.set_jpeg(thumbnail, In::THUMBNAIL);
+        let mut buf = Cursor::new(Vec::new());
+        writer.write(&mut buf, false).unwrap();
+
+        let mut jpeg = Jpeg::from_bytes(fs::read(path).unwrap().into()).unwrap();
+        jpeg.segments_mut().insert(
+            0,
+            JpegSegment::new_with_contents(markers::APP1, prefixed(EXIF_PREFIX, buf.get_ref())),
+        );
+        jpeg.encoder().write_to(fs::File::create(path).unwrap()).unwrap();
+    }
+
+    fn read_exif(path: &Path) -> exif::Exif {
+        exif::Reader::new()
+            .read_from_container(&mut std::io::BufReader::new(fs::File::open(path).unwrap()))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_embed_keeps_exif() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("image.jpg");
+        let thumbnail = b"\xff\xd8thumbnail\xff\xd9";
+        let fields = [
+            ascii(Tag::Make, "Camera Maker"),
+            Field {
+                tag: Tag::ExposureTime,
+                ifd_num: In::PRIMARY,
+                value: Value::Rational(vec![(1, 250).into()]),
+            },
+            Field {
+                tag: Tag::Orientation,
+                ifd_num: In::THUMBNAIL,
+                value: Value::Short(vec![1]),
+            },
+        ];
+        jpeg_with_exif(&path, &fields, thumbnail);
+
+        let mut metadata = Metadata::new(Source::Bing, "image.jpg");
+        metadata.copyright = Some("© Photographer".to_string());
+        assert!(embed(&path, &metadata).unwrap());
+
+        let exif = read_exif(&path);
+        assert_eq!(
+            exif.get_field(Tag::Make, In::PRIMARY)
+                .unwrap()
+                .display_value()
+                .to_string(),
+            "\"Camera Maker\""
+        );
+        assert_eq!(
+            exif.get_field(Tag::ExposureTime, In::PRIMARY)
+                .unwrap()
+                .display_value()
+                .to_string(),
+            "1/250"
+        );
+        assert!(exif.get_field(Tag::Orientation, In::THUMBNAIL).is_some());
+        assert_eq!(super::thumbnail(&exif), Some(&thumbnail[..]));
+        assert!(exif.get_field(Tag::Artist, In::PRIMARY).is_some());
+
+        // EXIF data with a maker note is left as is.
+        let fields = [Field {
+            tag: Tag::MakerNote,
+            ifd_num: In::PRIMARY,
+            value: Value::Undefined(b"private".to_vec(), 0),
+        }];
+        jpeg_with_exif(&path, &fields, thumbnail);
+        let before = read_exif(&path).buf().to_vec();
+        assert!(embed(&path, &metadata).unwrap());
+        let exif = read_exif(&path);
+        assert_eq!(exif.buf(), &before[..]);
+        assert!(exif.get_field(Tag::Artist, In::PRIMARY).is_none());
+    }
+}
@@ -0,0 +1,379 @@
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::iter::Peekable;
+use std::str::FromStr;
+use std::vec::IntoIter;
+
+use crate::metadata::{Metadata, Orientation};
+use crate::tone::Tone;
+
+/// Wallpaper filter.
+///
+/// Predicates on fields a source does not provide pass, e.g. `Wallpaper` always passes for Spotlight.
+///
+/// Filters can be parsed from expressions such as
+/// `min-size=1920x1080 & (market=en-US | market=ja-JP) & !keyword=bridge`. Predicates are:
+///
+/// - `min-size=WxH`, `max-size=WxH`
+/// - `ratio=MIN..MAX`, where bounds are optional and can be written as `16:9` or `1.78`
+/// - `orientation=landscape`, `orientation=portrait`
+/// - `file-size=MIN..MAX`, where bounds are optional and accept `K`, `M` and `G` suffixes
+/// - `wallpaper`, `wallpaper=false`
+/// - `dark`, `light` (not dark)
+/// - `tone=dark`, `tone=light`, `tone=mixed`
+/// - `market=en-US`
+/// - `keyword=lighthouse`, or `keyword="golden gate"` for keywords with spaces
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Filter {
+    MinSize { width: u32, height: u32 },
+    MaxSize { width: u32, height: u32 },
+    AspectRatio { min: Option<f64>, max: Option<f64> },
+    Orientation(Orientation),
+    FileSize { min: Option<u64>, max: Option<u64> },
+    Wallpaper(bool),
+    Dark(bool),
+    Tone(Tone),
+    Market(String),
+    Keyword(String),
+    All(Vec<Filter>),
+    Any(Vec<Filter>),
+    Not(Box<Filter>),
+}
+
+impl Default for Filter {
+    /// Returns a filter that matches everything.
+    fn default() -> Self {
+        Filter::All(vec![])
+    }
+}
+
+fn in_range<T: PartialOrd>(value: T, min: Option<T>, max: Option<T>) -> bool {
+    min.is_none_or(|min| value >= min) && max.is_none_or(|max| value <= max)
+}
+
+impl Filter {
+    /// Returns whether the metadata matches the filter.
+    pub fn matches(&self, metadata: &Metadata) -> bool {
+        match self {
+            Filter::MinSize { width, height } => metadata.dimensions().is_none_or(|(w, h)| w >= *width && h >= *height),
+            Filter::MaxSize { width, height } => metadata.dimensions().is_none_or(|(w, h)| w <= *width && h <= *height),
+            Filter::AspectRatio { min, max } => metadata
+                .dimensions()
+                .filter(|(_, h)| *h > 0)
+                .is_none_or(|(w, h)| in_range(w as f64 / h as f64, *min, *max)),
+            Filter::Orientation(orientation) => metadata.orientation().is_none_or(|o| o == *orientation),
+            Filter::FileSize { min, max } => metadata.file_size.is_none_or(|size| in_range(size, *min, *max)),
+            Filter::Wallpaper(wallpaper) => metadata.wallpaper.is_none_or(|wp| wp == *wallpaper),
+            Filter::Dark(dark) => metadata.tone.is_none_or(|tone| (tone == Tone::Dark) == *dark),
+            Filter::Tone(tone) => metadata.tone.is_none_or(|t| t == *tone),
+            Filter::Market(market) => metadata.market.as_ref().is_none_or(|m| m.eq_ignore_ascii_case(market)),
+            Filter::Keyword(keyword) => metadata
+                .title
+                .as_ref()
+                .is_none_or(|title| title.to_lowercase().contains(&keyword.to_lowercase())),
+            Filter::All(filters) => filters.iter().all(|filter| filter.matches(metadata)),
+            Filter::Any(filters) => filters.is_empty() || filters.iter().any(|filter| filter.matches(metadata)),
+            Filter::Not(filter) => !filter.matches(metadata),
+        }
+    }
+}
+
+/// Parses dimensions such as `1920x1080`.
+pub fn parse_dimensions(s: &str) -> Result<(u32, u32), Box<dyn Error>> {
+    let (width, height) = s
+        .split_once(['x', 'X'])
+        .ok_or_else(|| format!("invalid dimensions: {s}"))?;
+
+    Ok((width.trim().parse()?, height.trim().parse()?))
+}
+
+fn parse_ratio(s: &str) -> Result<f64, Box<dyn Error>> {
+    match s.split_once(':') {
+        Some((w, h)) => Ok(w.trim().parse::<f64>()? / h.trim().parse::<f64>()?),
+        None => Ok(s.trim().parse()?),
+    }
+}
+
+/// Parses file size with optional `K`, `M` or `G` suffix.
+pub fn parse_file_size(s: &str) -> Result<u64, Box<dyn Error>> {
+    let s = s.trim().trim_end_matches(['b', 'B']);
+    let (number, unit) = match s.char_indices().last() {
+        Some((i, c)) if c.is_ascii_alphabetic() => (&s[..i], c.to_ascii_uppercase()),
+        _ => (s, ' '),
+    };
+
+    let multiplier = match unit {
+        ' ' => 1,
+        'K' => 1 << 10,
+        'M' => 1 << 20,
+        'G' => 1 << 30,
+        _ => return Err(format!("invalid file size: {s}").into()),
+    };
+
+    Ok((number.trim().parse::<f64>()? * multiplier as f64) as u64)
+}
+
+fn parse_range<T>(
+    s: &str,
+    parse: impl Fn(&str) -> Result<T, Box<dyn Error>>,
+) -> Result<(Option<T>, Option<T>), Box<dyn Error>> {
+    let (min, max) = s.split_once("..").ok_or_else(|| format!("invalid range: {s}"))?;
+
+    let bound = |s: &str| match s.trim() {
+        "" => Ok(None),
+        s => parse(s).map(Some),
+    };
+
+    Ok((bound(min)?, bound(max)?))
+}
+
+fn parse_bool(s: &str) -> Result<bool, Box<dyn Error>> {
+    match s.to_ascii_lowercase().as_str() {
+        "true" | "yes" | "1" => Ok(true),
+        "false" | "no" | "0" => Ok(false),
+        _ => Err(format!("invalid boolean: {s}").into()),
+    }
+}
+
+fn parse_predicate(s: &str) -> Result<Filter, Box<dyn Error>> {
+    let (key, value) = match s.split_once('=') {
+        Some((key, value)) => (key.trim(), Some(value.trim().trim_matches('"'))),
+        None => (s.trim(), None),
+    };
+
+    let flag = || value.map_or(Ok(true), parse_bool);
+    let value = || value.ok_or_else(|| format!("missing value for {key}"));
+
+    let filter = match key.to_ascii_lowercase().as_str() {
+        "min-size" => {
+            let (width, height) = parse_dimensions(value()?)?;
+            Filter::MinSize { width, height }
+        }
+        "max-size" => {
+            let (width, height) = parse_dimensions(value()?)?;
+            Filter::MaxSize { width, height }
+        }
+        "ratio" => {
+            let (min, max) = parse_range(value()?, parse_ratio)?;
+            Filter::AspectRatio { min, max }
+        }
+        "orientation" => Filter::Orientation(value()?.parse()?),
+        "file-size" => {
+            let (min, max) = parse_range(value()?, parse_file_size)?;
+            Filter::FileSize { min, max }
+        }
+        "wallpaper" => Filter::Wallpaper(flag()?),
+        "dark" => Filter::Dark(flag()?),
+        "light" => Filter::Dark(!flag()?),
+        "tone" => Filter::Tone(value()?.parse()?),
+        "market" => Filter::Market(value()?.to_string()),
+        "keyword" => Filter::Keyword(value()?.to_string()),
+        _ => return Err(format!("unknown filter: {key}").into()),
+    };
+
+    Ok(filter)
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+    Predicate(String),
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, Box<dyn Error>> {
+    let mut tokens = Vec::new();
+    let mut chars = s.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        let token = match c {
+            '&' => Token::And,
+            '|' => Token::Or,
+            '!' => Token::Not,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            c if c.is_whitespace() => {
+                chars.next();
+                continue;
+            }
+            _ => {
+                let mut predicate = String::new();
+                let mut quoted = false;
+
+                while let Some(&c) = chars.peek() {
+                    if !quoted && (c.is_whitespace() || "&|!()".contains(c)) {
+                        break;
+                    }
+                    if c == '"' {
+                        quoted = !quoted;
+                    }
+                    predicate.push(c);
+                    chars.next();
+                }
+
+                if quoted {
+                    return Err(format!("unterminated quote in {predicate}").into());
+                }
+
+                tokens.push(Token::Predicate(predicate));
+                continue;
+            }
+        };
+
+        chars.next();
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Peekable<IntoIter<Token>>,
+}
+
+impl Parser {
+    // expr := term ('|' term)*
+    fn expr(&mut self) -> Result<Filter, Box<dyn Error>> {
+        let mut filters = vec![self.term()?];
+        while self.tokens.next_if_eq(&Token::Or).is_some() {
+            filters.push(self.term()?);
+        }
+
+        Ok(if filters.len() == 1 {
+            filters.remove(0)
+        } else {
+            Filter::Any(filters)
+        })
+    }
+
+    // term := factor ('&' factor)*
+    fn term(&mut self) -> Result<Filter, Box<dyn Error>> {
+        let mut filters = vec![self.factor()?];
+        while self.tokens.next_if_eq(&Token::And).is_some() {
+            filters.push(self.factor()?);
+        }
+
+        Ok(if filters.len() == 1 {
+            filters.remove(0)
+        } else {
+            Filter::All(filters)
+        })
+    }
+
+    // factor := '!' factor | '(' expr ')' | predicate
+    fn factor(&mut self) -> Result<Filter, Box<dyn Error>> {
+        match self.tokens.next() {
+            Some(Token::Not) => Ok(Filter::Not(Box::new(self.factor()?))),
+            Some(Token::Open) => {
+                let filter = self.expr()?;
+                match self.tokens.next() {
+                    Some(Token::Close) => Ok(filter),
+                    _ => Err("missing closing parenthesis".into()),
+                }
+            }
+            Some(Token::Predicate(predicate)) => parse_predicate(&predicate),
+            Some(token) => Err(format!("unexpected {token:?}").into()),
+            None => Err("unexpected end of filter".into()),
+        }
+    }
+}
+
+impl FromStr for Filter {
+    type Err = Box<dyn Error + Send + Sync>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = || -> Result<Filter, Box<dyn Error>> {
+            let mut parser = Parser {
+                tokens: tokenize(s)?.into_iter().peekable(),
+            };
+
+            let filter = parser.expr()?;
+            if let Some(token) = parser.tokens.next() {
+                return Err(format!("unexpected {token:?}").into());
+            }
+
+            Ok(filter)
+        };
+
+        parse().map_err(|e| e.to_string().into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::Source;
+
+    #[test]
+    fn test_parse() {
+        let filter: Filter = "min-size=1920x1080 & (market=en-US | market=ja-JP) & !keyword=\"golden gate\""
+            .parse()
+            .unwrap();
+
+        assert_eq!(
+            filter,
+            Filter::All(vec![
+                Filter::MinSize {
+                    width: 1920,
+                    height: 1080
+                },
+                Filter::Any(vec![
+                    Filter::Market("en-US".to_string()),
+                    Filter::Market("ja-JP".to_string()),
+                ]),
+                Filter::Not(Box::new(Filter::Keyword("golden gate".to_string()))),
+            ])
+        );
+
+        assert_eq!(
+            "ratio=16:10.. | file-size=..2M".parse::<Filter>().unwrap(),
+            Filter::Any(vec![
+                Filter::AspectRatio {
+                    min: Some(1.6),
+                    max: None
+                },
+                Filter::FileSize {
+                    min: None,
+                    max: Some(2 << 20)
+                },
+            ])
+        );
+
+        assert!("min-size=1920x1080 &".parse::<Filter>().is_err());
+        assert!("(dark".parse::<Filter>().is_err());
+        assert!("size=big".parse::<Filter>().is_err());
+    }
+
+    #[test]
+    fn test_matches() {
+        let mut metadata = Metadata::new(Source::Bing, "OHR.Lighthouse_EN-US1_UHD.jpg");
+        metadata.title = Some("The Lighthouse at Dawn".to_string());
+        metadata.market = Some("EN-US".to_string());
+        metadata.width = Some(3840);
+        metadata.height = Some(2160);
+
+        let filter: Filter = "min-size=1920x1080 & orientation=landscape & keyword=lighthouse & market=en-us"
+            .parse()
+            .unwrap();
+        assert!(filter.matches(&metadata));
+
+        assert!(!"orientation=portrait".parse::<Filter>().unwrap().matches(&metadata));
+        assert!(!"max-size=1920x1080".parse::<Filter>().unwrap().matches(&metadata));
+
+        // Unknown fields pass.
+        assert!(
+            "wallpaper & file-size=1M.."
+                .parse::<Filter>()
+                .unwrap()
+                .matches(&metadata)
+        );
+
+        metadata.tone = Some(Tone::Mixed);
+        assert!("light & tone=mixed".parse::<Filter>().unwrap().matches(&metadata));
+        assert!(!"dark".parse::<Filter>().unwrap().matches(&metadata));
+    }
+}
@@ -0,0 +1,129 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use crate::metadata::{Metadata, iso_date};
+use crate::template::sanitize;
+
+/// Directory of wallpapers whose value is unknown.
+pub const UNKNOWN_DIR: &str = "unknown";
+
+/// How saved wallpapers are arranged in directories.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Layout {
+    /// All wallpapers in one directory.
+    #[default]
+    Flat,
+    /// `bing/`, `spotlight/`
+    Source,
+    /// `2024/01/`, by the date of the wallpaper or else when it was first seen.
+    YearMonth,
+    /// `en-US/`
+    Market,
+    /// `landscape/`, `portrait/`
+    Orientation,
+}
+
+impl FromStr for Layout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "flat" => Ok(Layout::Flat),
+            "source" => Ok(Layout::Source),
+            "year-month" => Ok(Layout::YearMonth),
+            "market" => Ok(Layout::Market),
+            "orientation" => Ok(Layout::Orientation),
+            _ => Err(format!("unknown layout: {s}")),
+        }
+    }
+}
+
+impl fmt::Display for Layout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Layout::Flat => write!(f, "flat"),
+            Layout::Source => write!(f, "source"),
+            Layout::YearMonth => write!(f, "year-month"),
+            Layout::Market => write!(f, "market"),
+            Layout::Orientation => write!(f, "orientation"),
+        }
+    }
+}
+
+/// Returns year and month of seconds since the Unix epoch, in UTC.
+fn year_month(secs: u64) -> (i64, u32) {
+    // https://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let days = (secs / 86400) as i64 + 719468;
+    let era = days.div_euclid(146097);
+    let doe = days.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month)
+}
+
+impl Layout {
+    /// Returns directory, relative to the library root, of a wallpaper first seen at `first_seen` seconds since the
+    /// Unix epoch.
+    pub fn dir(self, metadata: &Metadata, first_seen: u64) -> PathBuf {
+        let known = |value: Option<String>| {
+            PathBuf::from(
+                value
+                    .filter(|value| !value.is_empty())
+                    .map_or(UNKNOWN_DIR.to_string(), |value| sanitize(&value)),
+            )
+        };
+
+        match self {
+            Layout::Flat => PathBuf::new(),
+            Layout::Source => PathBuf::from(metadata.source.to_string()),
+            Layout::YearMonth => {
+                let date = metadata.date.as_deref().map(iso_date);
+                let parts = date
+                    .as_deref()
+                    .and_then(|date| date.split_once('-'))
+                    .and_then(|(year, rest)| Some((year.parse::<i64>().ok()?, rest.get(..2)?.parse::<u32>().ok()?)));
+
+                let (year, month) = parts.unwrap_or_else(|| year_month(first_seen));
+                PathBuf::from(year.to_string()).join(format!("{month:02}"))
+            }
+            Layout::Market => known(metadata.market.clone()),
+            Layout::Orientation => known(metadata.orientation().map(|orientation| orientation.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::Source;
+
+    #[test]
+    fn test_dir() {
+        let mut metadata = Metadata::new(Source::Spotlight, "asset");
+        // 2021-03-04
+        let first_seen = 1614816000;
+
+        assert_eq!(Layout::Flat.dir(&metadata, first_seen), PathBuf::new());
+        assert_eq!(Layout::Source.dir(&metadata, first_seen), PathBuf::from("spotlight"));
+        assert_eq!(Layout::YearMonth.dir(&metadata, first_seen), PathBuf::from("2021/03"));
+        assert_eq!(Layout::Market.dir(&metadata, first_seen), PathBuf::from(UNKNOWN_DIR));
+
+        metadata.date = Some("20240131".to_string());
+        metadata.market = Some("en-US".to_string());
+        metadata.width = Some(1080);
+        metadata.height = Some(1920);
+        assert_eq!(Layout::YearMonth.dir(&metadata, first_seen), PathBuf::from("2024/01"));
+        assert_eq!(Layout::Market.dir(&metadata, first_seen), PathBuf::from("en-US"));
+        assert_eq!(
+            Layout::Orientation.dir(&metadata, first_seen),
+            PathBuf::from("portrait")
+        );
+    }
+}
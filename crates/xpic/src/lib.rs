@@ -1,6 +1,30 @@
 pub mod bing;
+pub mod caption;
+pub mod catalog;
+pub mod config;
+pub mod convert;
+pub mod dedup;
+pub mod derived;
+pub mod effect;
+pub mod embed;
+pub mod filter;
+pub mod layout;
+pub mod library;
+pub mod metadata;
+pub mod palette;
+pub mod phash;
+pub mod resize;
+pub mod retention;
+pub mod saliency;
+pub mod scheme;
+pub mod sidecar;
+pub mod span;
 pub mod spotlight;
+pub mod template;
+pub mod thumbnail;
+pub mod tone;
 pub mod util;
+pub mod verify;
 
 #[cfg(test)]
 mod tests {
@@ -0,0 +1,736 @@
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use crate::bing::ImageDetail;
+use crate::catalog::{self, Catalog, Entry, Search};
+use crate::config::SaveOptions;
+use crate::derived::Kind;
+use crate::embed;
+use crate::layout::Layout;
+use crate::metadata::{Metadata, Source};
+use crate::palette::{self, Palette};
+use crate::phash::PerceptualHash;
+use crate::sidecar;
+use crate::template::{self, Template};
+use crate::tone::{self, Tone};
+use crate::util;
+use crate::util::Saved;
+
+/// Directory inside a library where xpic keeps its own data.
+pub const DATA_DIR: &str = ".xpic";
+
+/// Catalog file inside the data directory.
+pub const CATALOG_FILE: &str = "catalog.db";
+
+/// Directory inside the data directory for downloads that are not named yet.
+pub const DOWNLOADS_DIR: &str = "downloads";
+
+/// A directory of saved wallpapers and its catalog.
+///
+/// Entries returned by a library have absolute paths; the catalog stores them relative to the root.
+pub struct Library {
+    root: PathBuf,
+    catalog: Catalog,
+}
+
+impl Library {
+    /// Opens or creates a library.
+    pub fn open(root: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        let root = root.as_ref().to_path_buf();
+        let data_dir = root.join(DATA_DIR);
+
+        fs::create_dir_all(&data_dir).map_err(|err| format!("failed to create {}: {}", data_dir.display(), err))?;
+
+        let catalog = Catalog::open(data_dir.join(CATALOG_FILE))?;
+
+        Ok(Self { root, catalog })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn data_dir(&self) -> PathBuf {
+        self.root.join(DATA_DIR)
+    }
+
+    pub fn downloads_dir(&self) -> PathBuf {
+        self.data_dir().join(DOWNLOADS_DIR)
+    }
+
+    pub fn catalog(&self) -> &Catalog {
+        &self.catalog
+    }
+
+    /// Returns path relative to the library root.
+    pub fn relative(&self, path: impl AsRef<Path>) -> Result<PathBuf, Box<dyn Error>> {
+        let path = path.as_ref();
+
+        path.strip_prefix(&self.root)
+            .map(Path::to_path_buf)
+            .map_err(|_| format!("{} is not in {}", path.display(), self.root.display()).into())
+    }
+
+    fn absolute(&self, mut entry: Entry) -> Entry {
+        entry.path = self.root.join(&entry.path);
+        entry
+    }
+
+    /// Returns directory where a new wallpaper is saved under the layout, creating it if needed.
+    pub fn layout_dir(&self, metadata: &Metadata, layout: Layout) -> Result<PathBuf, Box<dyn Error>> {
+        let dir = self.root.join(layout.dir(metadata, catalog::now()));
+
+        fs::create_dir_all(&dir).map_err(|e| format!("failed to create {}: {}", dir.display(), e))?;
+
+        Ok(dir)
+    }
+
+    /// Adds or updates the entry of a file in the library. Dimensions and file size are read from the file.
+    pub fn add(&self, mut metadata: Metadata, path: impl AsRef<Path>) -> Result<Entry, Box<dyn Error>> {
+        let path = path.as_ref();
+
+        let (width, height) = util::get_image_dimensions(path)?;
+        metadata.width = Some(width);
+        metadata.height = Some(height);
+        let file = fs::metadata(path)?;
+        metadata.file_size = Some(file.len());
+        let modified = file
+            .modified()
+            .ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map(|modified| modified.as_nanos() as u64);
+
+        let relative = self.relative(path)?;
+        let existing = self.catalog.find_by_path(&relative)?;
+
+        // A file of the same size and modification time as when it was last hashed is taken to be unchanged.
+        let untouched = |entry: &Entry| {
+            modified.is_some() && entry.modified == modified && entry.metadata.file_size == metadata.file_size
+        };
+        let hash = match &existing {
+            Some(entry) if untouched(entry) => entry.hash.clone(),
+            _ => util::hash_file(path)?,
+        };
+
+        // The perceptual hash, palette and measured tone only change with the content.
+        let changed = existing.is_none_or(|entry| entry.hash != hash);
+        let hinted = metadata.tone.is_some();
+
+        let mut entry = self.catalog.upsert(&metadata, &hash, relative)?;
+        if entry.modified != modified {
+            self.catalog.set_modified(entry.id, modified)?;
+            entry.modified = modified;
+        }
+
+        let analyze =
+            changed || entry.phash.is_none() || entry.metadata.palette.is_none() || entry.metadata.tone.is_none();
+        if !analyze {
+            return Ok(self.absolute(entry));
+        }
+
+        // AVIF is only encoded, so it cannot be analyzed.
+        if util::get_image_format(path)? == image::ImageFormat::Avif {
+            return Ok(self.absolute(entry));
+        }
+
+        let img = match util::open_image(path) {
+            Ok(img) => img,
+            Err(err) => {
+                eprintln!("failed to analyze image {}: {}", path.display(), err);
+                return Ok(self.absolute(entry));
+            }
+        };
+
+        if changed || entry.phash.is_none() {
+            let phash = PerceptualHash::of(&img.thumbnail(64, 64));
+            self.catalog.set_phash(entry.id, phash)?;
+            entry.phash = Some(phash);
+        }
+
+        if changed || entry.metadata.palette.is_none() {
+            let palette = Palette::of(&img);
+            self.catalog.set_palette(entry.id, &palette)?;
+            entry.metadata.palette = Some(palette);
+        }
+
+        if (changed && !hinted) || entry.metadata.tone.is_none() {
+            let tone = Tone::measure(&img);
+            self.catalog.set_tone(entry.id, tone)?;
+            entry.metadata.tone = Some(tone);
+        }
+
+        Ok(self.absolute(entry))
+    }
+
+    /// Returns an entry of the image from the source whose file is still present.
+    pub fn find_by_source(&self, source: Source, source_id: &str) -> Result<Option<Entry>, Box<dyn Error>> {
+        Ok(self
+            .catalog
+            .find_by_source(source, source_id)?
+            .into_iter()
+            .map(|entry| self.absolute(entry))
+            .find(|entry| entry.path.exists()))
+    }
+
+    /// Returns an entry with the content hash whose file is still present.
+    pub fn find_by_hash(&self, hash: &str) -> Result<Option<Entry>, Box<dyn Error>> {
+        Ok(self
+            .catalog
+            .find_by_hash(hash)?
+            .into_iter()
+            .map(|entry| self.absolute(entry))
+            .find(|entry| entry.path.exists()))
+    }
+
+    /// Copies a file into the library, naming it by content hash, in the directory given by the layout.
+    ///
+    /// Files whose content is already in the library, wherever they are, are not written again.
+    pub fn copy_by_hash(
+        &self,
+        src: impl AsRef<Path>,
+        metadata: &Metadata,
+        layout: Layout,
+    ) -> Result<Saved, Box<dyn Error>> {
+        let src = src.as_ref();
+
+        if let Some(entry) = self.find_by_hash(&util::hash_file(src)?)? {
+            return Ok(Saved::Existing(entry.path));
+        }
+
+        util::copy_image_by_hash(src, self.layout_dir(metadata, layout)?)
+    }
+
+    /// Copies or moves a file into the library under the name given by the template, or its own name without one, in
+    /// the directory given by the layout. Dimensions missing from the metadata are read from the file.
+    ///
+    /// Files whose content is already in the library are not written again, and taken names are numbered.
+    pub fn place(
+        &self,
+        src: impl AsRef<Path>,
+        metadata: &Metadata,
+        template: Option<&Template>,
+        layout: Layout,
+        keep_src: bool,
+    ) -> Result<Saved, Box<dyn Error>> {
+        let src = src.as_ref();
+        let hash = util::hash_file(src)?;
+
+        if let Some(entry) = self.find_by_hash(&hash)? {
+            if !keep_src {
+                fs::remove_file(src)?;
+            }
+            return Ok(Saved::Existing(entry.path));
+        }
+
+        let mut metadata = metadata.clone();
+        if metadata.dimensions().is_none() {
+            let (width, height) = util::get_image_dimensions(src)?;
+            metadata.width = Some(width);
+            metadata.height = Some(height);
+        }
+
+        let name = match template {
+            Some(template) => template.render(&metadata, &hash, util::get_image_extension(src)?),
+            None => PathBuf::from(src.file_name().ok_or("failed to get filename")?),
+        };
+        let dst = self.layout_dir(&metadata, layout)?.join(name);
+
+        template::place(src, dst, keep_src)
+    }
+
+    /// Moves files of all entries, with their sidecars, to the directories given by the layout and updates the
+    /// catalog. Returns the moves; with `dry_run` nothing is moved.
+    ///
+    /// Taken names are numbered, and a file whose content is already at its new path is removed with its entry.
+    pub fn reorganize(&self, layout: Layout, dry_run: bool) -> Result<Vec<(PathBuf, PathBuf)>, Box<dyn Error>> {
+        let mut moves = Vec::new();
+
+        for entry in self.list()? {
+            let Some(name) = entry.path.file_name() else {
+                continue;
+            };
+
+            let dst = self.root.join(layout.dir(&entry.metadata, entry.first_seen)).join(name);
+
+            if dst == entry.path {
+                continue;
+            }
+
+            if !entry.path.exists() {
+                eprintln!("{} is missing", entry.path.display());
+                continue;
+            }
+
+            if dry_run {
+                moves.push((entry.path, dst));
+                continue;
+            }
+
+            let saved = template::place(&entry.path, &dst, false)?;
+
+            match &saved {
+                Saved::Existing(path) if self.find_by_path(path)?.is_some() => self.catalog.remove(entry.id)?,
+                _ => self.catalog.set_path(entry.id, self.relative(saved.path())?)?,
+            }
+
+            self.move_companions(entry.id, &entry.path, saved.path())?;
+
+            moves.push((entry.path, saved.path().to_path_buf()));
+        }
+
+        if !dry_run {
+            remove_empty_dirs(&self.root)?;
+        }
+
+        Ok(moves)
+    }
+
+    /// Records a saved file, embedding credits into and converting new files, and writing sidecars as configured.
+    ///
+    /// Entries of the same wallpaper whose file is gone are replaced by the new one, which keeps their marks.
+    pub fn store(&self, metadata: Metadata, saved: &Saved, save: &SaveOptions) -> Result<Entry, Box<dyn Error>> {
+        let original = saved.path().to_path_buf();
+        let mut path = original.clone();
+
+        // Embed and convert before adding, so that the catalog has the hash of the final file. Credits embedded
+        // into the original are carried over by the conversion.
+        if saved.is_new() {
+            if save.embed {
+                embed::embed(&path, &metadata)?;
+            }
+
+            if let Some(convert) = &save.convert {
+                path = convert.convert(&path)?;
+            }
+        }
+
+        let mut entry = self.add(metadata, &path)?;
+
+        if saved.is_new() {
+            // A kept original goes with the converted file, and is not added again by `scan`.
+            if path != original && original.exists() {
+                self.add_derived(&entry, &original, Kind::Original)?;
+            }
+
+            if self.replace_missing(&entry)? {
+                entry = self.entry(&entry.path)?;
+            }
+
+            for resize in &save.resize {
+                let result = resize.resize(&entry.path, entry.metadata.focus);
+                if let Err(err) = result.and_then(|path| self.add_derived(&entry, path, Kind::Resize)) {
+                    eprintln!("failed to resize {}: {}", entry.path.display(), err);
+                }
+            }
+
+            if let Some(caption) = &save.caption {
+                let result = caption.caption(&entry.path, &entry.metadata);
+                if let Err(err) = result.and_then(|path| self.add_derived(&entry, path, Kind::Caption)) {
+                    eprintln!("failed to caption {}: {}", entry.path.display(), err);
+                }
+            }
+
+            for effect in &save.effects {
+                let result = effect.derive(&entry.path);
+                if let Err(err) = result.and_then(|path| self.add_derived(&entry, path, Kind::Effect)) {
+                    eprintln!("failed to apply {} to {}: {}", effect, entry.path.display(), err);
+                }
+            }
+        }
+        sidecar::write(&entry.path, &entry.metadata, &save.sidecars)?;
+
+        Ok(entry)
+    }
+
+    /// Replaces entries of the same wallpaper as `entry` whose file is gone, carrying over their favorite and hidden
+    /// marks, tags and recorded files. Returns whether any were replaced.
+    fn replace_missing(&self, entry: &Entry) -> Result<bool, Box<dyn Error>> {
+        let mut replaced = false;
+
+        for stale in self.catalog.find_by_source(entry.metadata.source, &entry.metadata.id)? {
+            if stale.id == entry.id || self.root.join(&stale.path).exists() {
+                continue;
+            }
+
+            if stale.favorite {
+                self.catalog.set_favorite(entry.id, true)?;
+            }
+            if stale.hidden {
+                self.catalog.set_hidden(entry.id, true)?;
+            }
+            for tag in &stale.tags {
+                self.catalog.add_tag(entry.id, tag)?;
+            }
+            for (path, kind) in self.catalog.derived(stale.id)? {
+                self.catalog.add_derived(entry.id, path, kind)?;
+            }
+            self.catalog.remove(stale.id)?;
+            replaced = true;
+        }
+
+        Ok(replaced)
+    }
+
+    /// Records a file derived from the file of an entry, so that it goes with the entry and is not cataloged on its
+    /// own. A copy that failed or was skipped is not recorded.
+    pub fn add_derived(&self, entry: &Entry, path: impl AsRef<Path>, kind: Kind) -> Result<(), Box<dyn Error>> {
+        self.catalog.add_derived(entry.id, self.relative(path)?, kind)
+    }
+
+    /// Deletes the file of an entry, its sidecars and derived files, and removes the entry from the catalog.
+    pub fn remove(&self, entry: &Entry) -> Result<(), Box<dyn Error>> {
+        let path = self.root.join(&entry.path);
+        let sidecars = sidecar::FORMATS.map(|format| sidecar::path(&path, format));
+        let derived = self
+            .catalog
+            .derived(entry.id)?
+            .into_iter()
+            .map(|(derived, _)| self.root.join(derived));
+
+        for path in [path.clone()].into_iter().chain(sidecars).chain(derived) {
+            if path.exists() {
+                fs::remove_file(&path).map_err(|e| format!("failed to remove {}: {}", path.display(), e))?;
+            }
+        }
+
+        self.catalog.remove(entry.id)
+    }
+
+    /// Moves the file of an entry and its sidecars out of the library into dir, and removes the entry from the
+    /// catalog. Returns the new path.
+    pub fn move_to(&self, entry: &Entry, dir: impl AsRef<Path>) -> Result<PathBuf, Box<dyn Error>> {
+        let path = self.root.join(&entry.path);
+        let name = path.file_name().ok_or("failed to get filename")?;
+
+        let saved = template::place(&path, dir.as_ref().join(name), false)?;
+        self.move_companions(entry.id, &path, saved.path())?;
+        self.catalog.remove(entry.id)?;
+
+        Ok(saved.path().to_path_buf())
+    }
+
+    /// Returns all entries, most recently seen first.
+    pub fn list(&self) -> Result<Vec<Entry>, Box<dyn Error>> {
+        Ok(self
+            .catalog
+            .list()?
+            .into_iter()
+            .map(|entry| self.absolute(entry))
+            .collect())
+    }
+
+    /// Returns entries matching the search, best matches first.
+    pub fn search(&self, search: &Search) -> Result<Vec<Entry>, Box<dyn Error>> {
+        Ok(self
+            .catalog
+            .search(search)?
+            .into_iter()
+            .map(|entry| self.absolute(entry))
+            .collect())
+    }
+
+    pub fn find_by_path(&self, path: impl AsRef<Path>) -> Result<Option<Entry>, Box<dyn Error>> {
+        Ok(self
+            .catalog
+            .find_by_path(self.relative(path)?)?
+            .map(|entry| self.absolute(entry)))
+    }
+
+    /// Returns the entry of a file, failing if the file is not in the catalog.
+    pub fn entry(&self, path: impl AsRef<Path>) -> Result<Entry, Box<dyn Error>> {
+        let path = path.as_ref();
+
+        self.find_by_path(path)?
+            .ok_or_else(|| format!("{} is not in the catalog", path.display()).into())
+    }
+
+    /// Returns the tone of an entry, measuring and recording it first if it is unknown.
+    pub fn tone(&self, entry: &Entry) -> Result<Tone, Box<dyn Error>> {
+        if let Some(tone) = entry.metadata.tone {
+            return Ok(tone);
+        }
+
+        let tone = tone::tone(&entry.path)?;
+        self.catalog.set_tone(entry.id, tone)?;
+        Ok(tone)
+    }
+
+    /// Returns the palette of an entry, extracting and recording it first if it is missing.
+    pub fn palette(&self, entry: &Entry) -> Result<Palette, Box<dyn Error>> {
+        if let Some(palette) = &entry.metadata.palette {
+            return Ok(palette.clone());
+        }
+
+        let palette = palette::palette(&entry.path)?;
+        self.catalog.set_palette(entry.id, &palette)?;
+
+        // Sidecars already written are rewritten, so that they carry the palette too.
+        let mut metadata = entry.metadata.clone();
+        metadata.palette = Some(palette.clone());
+        let formats = sidecar::FORMATS
+            .into_iter()
+            .filter(|&format| sidecar::path(&entry.path, format).exists())
+            .collect::<Vec<_>>();
+        sidecar::write(&entry.path, &metadata, &formats)?;
+
+        Ok(palette)
+    }
+
+    /// Moves sidecars and files derived from the entry, such as resized copies, of an image that was moved from `from`
+    /// to `to`. Files already at the destination win.
+    fn move_companions(&self, id: i64, from: &Path, to: &Path) -> Result<(), Box<dyn Error>> {
+        let sidecars = sidecar::FORMATS.map(|format| (sidecar::path(from, format), sidecar::path(to, format)));
+        let derived = self
+            .catalog
+            .derived(id)?
+            .into_iter()
+            .map(|(derived, _)| {
+                let derived = self.root.join(derived);
+                let moved = companion_path(from, to, &derived);
+                (derived, moved)
+            })
+            .collect::<Vec<_>>();
+
+        for (from, to) in sidecars {
+            if !from.exists() || from == to {
+                continue;
+            }
+
+            if to.exists() {
+                fs::remove_file(&from)?;
+            } else if fs::rename(&from, &to).is_err() {
+                fs::copy(&from, &to)?;
+                fs::remove_file(&from)?;
+            }
+        }
+
+        for (from, to) in derived {
+            if from.exists() && from != to {
+                let saved = template::place(&from, &to, false)?;
+                if let (Ok(from), Ok(to)) = (self.relative(&from), self.relative(saved.path())) {
+                    self.catalog.set_derived_path(from, to)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Adds images in the library that are not in the catalog yet, and returns how many were added.
+    ///
+    /// Files named like Bing images are attributed to Bing, everything else to Windows Spotlight.
+    pub fn scan(&self) -> Result<usize, Box<dyn Error>> {
+        let mut added = 0;
+
+        for path in util::walk_files(&self.root)? {
+            if self.find_by_path(&path)?.is_some()
+                || self.catalog.is_derived(self.relative(&path)?)?
+                || util::get_image_format(&path).is_err()
+            {
+                continue;
+            }
+
+            let name = path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+
+            let metadata = match name.parse::<ImageDetail>() {
+                Ok(detail) if !detail.name.is_empty() => {
+                    let mut metadata = Metadata::new(Source::Bing, &name);
+                    metadata.market = Some(detail.market);
+                    metadata
+                }
+                _ => Metadata::new(Source::Spotlight, &name),
+            };
+
+            match self.add(metadata, &path) {
+                Ok(_) => added += 1,
+                Err(err) => eprintln!("failed to add {}: {}", path.display(), err),
+            }
+        }
+
+        Ok(added)
+    }
+}
+
+/// Returns where a file kept next to an image that moved from `from` to `to` goes: next to `to`, with the name of
+/// `from` replaced by that of `to`, e.g. `b.jpg` for `a.jpg` when `a.webp` moved to `b.webp`.
+fn companion_path(from: &Path, to: &Path, file: &Path) -> PathBuf {
+    let name = file.file_name().unwrap_or_default().to_string_lossy();
+    let from_stem = from.file_stem().unwrap_or_default().to_string_lossy();
+    let to_stem = to.file_stem().unwrap_or_default().to_string_lossy();
+
+    let name = match name.strip_prefix(from_stem.as_ref()) {
+        Some(rest) => format!("{to_stem}{rest}"),
+        None => name.into_owned(),
+    };
+
+    to.with_file_name(name)
+}
+
+/// Removes empty directories under dir, except hidden ones such as the data directory.
+fn remove_empty_dirs(dir: &Path) -> Result<bool, Box<dyn Error>> {
+    let mut empty = true;
+
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+
+        let hidden = path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+
+        if path.is_dir() && !hidden && remove_empty_dirs(&path)? {
+            fs::remove_dir(&path)?;
+            continue;
+        }
+
+        empty = false;
+    }
+
+    Ok(empty)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::convert::{self, Convert};
+    use crate::resize::Resize;
+    use image::{Rgb, RgbImage};
+
+    #[test]
+    fn test_place() {
+        let dir = tempfile::tempdir().unwrap();
+        let library = Library::open(dir.path()).unwrap();
+
+        // Downloaded images come without dimensions, which are read from the file.
+        fs::create_dir_all(library.downloads_dir()).unwrap();
+        let download = library.downloads_dir().join("portrait.jpg");
+        RgbImage::from_pixel(32, 64, Rgb([0, 128, 255]))
+            .save(&download)
+            .unwrap();
+
+        let metadata = Metadata::new(Source::Spotlight, "portrait.jpg");
+        let saved = library
+            .place(&download, &metadata, None, Layout::Orientation, false)
+            .unwrap();
+        assert!(saved.is_new());
+        assert_eq!(saved.path(), dir.path().join("portrait/portrait.jpg"));
+        assert!(!download.exists());
+    }
+
+    #[test]
+    fn test_reorganize() {
+        let dir = tempfile::tempdir().unwrap();
+        let library = Library::open(dir.path()).unwrap();
+        let save = SaveOptions {
+            sidecars: vec![sidecar::Format::Json],
+            resize: vec![Resize::new(32, 18)],
+            ..Default::default()
+        };
+
+        let mut bing = Metadata::new(Source::Bing, "bing");
+        bing.title = Some("Lighthouse".to_string());
+        bing.copyright = Some("© Photographer".to_string());
+        bing.date = Some("20240131".to_string());
+        let spotlight = Metadata::new(Source::Spotlight, "asset");
+
+        for (metadata, color) in [(bing, 0), (spotlight, 255)] {
+            let path = dir.path().join(format!("{}.png", metadata.id));
+            RgbImage::from_pixel(64, 32, Rgb([color, 128, 0])).save(&path).unwrap();
+            library.store(metadata, &Saved::New(path), &save).unwrap();
+        }
+
+        let moves = library.reorganize(Layout::YearMonth, false).unwrap();
+        assert_eq!(moves.len(), 2);
+
+        // The Bing wallpaper goes by its date, the undated asset by when it was first seen.
+        let bing = dir.path().join("2024/01/bing.png");
+        let spotlight = library
+            .find_by_source(Source::Spotlight, "asset")
+            .unwrap()
+            .unwrap()
+            .path;
+        assert!(moves.contains(&(dir.path().join("bing.png"), bing.clone())));
+        assert_ne!(spotlight.parent(), Some(dir.path()));
+        assert_eq!(
+            library.find_by_source(Source::Bing, "bing").unwrap().unwrap().path,
+            bing
+        );
+
+        // Companions come along.
+        assert!(sidecar::path(&bing, sidecar::Format::Json).exists());
+        assert!(Resize::new(32, 18).path(&bing).exists());
+        assert!(Resize::new(32, 18).path(&spotlight).exists());
+        assert!(!dir.path().join("bing.png.json").exists());
+        assert!(!Resize::new(32, 18).path(dir.path().join("bing.png")).exists());
+
+        assert!(library.reorganize(Layout::YearMonth, false).unwrap().is_empty());
+
+        // A wallpaper that is only named like a copy is not removed with the image.
+        let other = bing.with_file_name("bing_blur2.png");
+        RgbImage::from_pixel(64, 32, Rgb([0, 0, 255])).save(&other).unwrap();
+        library.add(Metadata::new(Source::Spotlight, "other"), &other).unwrap();
+        library.remove(&library.entry(&bing).unwrap()).unwrap();
+        assert!(!Resize::new(32, 18).path(&bing).exists());
+        assert!(other.exists());
+        assert_eq!(library.scan().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let library = Library::open(dir.path()).unwrap();
+        let save = SaveOptions {
+            sidecars: vec![sidecar::Format::Json],
+            convert: Some(Convert {
+                keep_original: true,
+                ..Convert::new(convert::Format::Webp)
+            }),
+            ..Default::default()
+        };
+
+        let mut metadata = Metadata::new(Source::Bing, "image");
+        metadata.title = Some("Lighthouse".to_string());
+        metadata.copyright = Some("© Photographer".to_string());
+
+        let png = dir.path().join("image.png");
+        RgbImage::from_pixel(64, 32, Rgb([0, 128, 255])).save(&png).unwrap();
+        let entry = library
+            .store(metadata.clone(), &Saved::New(png.clone()), &save)
+            .unwrap();
+        assert_eq!(entry.path, dir.path().join("image.webp"));
+        library.catalog().set_favorite(entry.id, true).unwrap();
+
+        // The kept original is not cataloged on its own, and would not share a sidecar with the converted file.
+        assert!(png.exists());
+        assert_eq!(library.scan().unwrap(), 0);
+        let json = fs::read_to_string(sidecar::path(&entry.path, sidecar::Format::Json)).unwrap();
+        let json: Metadata = serde_json::from_str(&json).unwrap();
+        assert_eq!(json.palette, entry.metadata.palette);
+        assert!(json.palette.is_some());
+        assert_ne!(
+            sidecar::path(&entry.path, sidecar::Format::Json),
+            sidecar::path(&png, sidecar::Format::Json)
+        );
+
+        // A wallpaper whose file was deleted is saved again in place of its entry.
+        fs::remove_file(&entry.path).unwrap();
+        assert!(library.find_by_source(Source::Bing, "image").unwrap().is_none());
+        let other = dir.path().join("other.png");
+        RgbImage::from_pixel(64, 32, Rgb([0, 128, 255])).save(&other).unwrap();
+        let stored = library
+            .store(metadata, &Saved::New(other), &SaveOptions::default())
+            .unwrap();
+        assert!(stored.favorite);
+        assert_eq!(library.list().unwrap(), vec![stored.clone()]);
+
+        assert_eq!(library.scan().unwrap(), 0);
+
+        library.remove(&stored).unwrap();
+        assert!(library.list().unwrap().is_empty());
+        assert!(!png.exists());
+    }
+}
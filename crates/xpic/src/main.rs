@@ -1,17 +1,203 @@
 use clap::{Parser, Subcommand};
 use std::path::{Path, PathBuf};
+use std::time::Duration;
+use url::Url;
+use xpic::caption::{Background, Caption, Corner};
+use xpic::catalog::{Entry, Search};
+use xpic::config::{Config, SaveOptions};
+use xpic::convert::{self, Convert};
+use xpic::dedup::{self, Dedup, Preference};
+use xpic::derived::Kind;
+use xpic::effect::Effect;
+use xpic::filter::{Filter, parse_dimensions, parse_file_size};
+use xpic::layout::Layout;
+use xpic::library::Library;
+use xpic::metadata::Source;
+use xpic::palette::Color;
+use xpic::resize::{Focus, Resize};
+use xpic::retention::{self, Retention};
+use xpic::scheme::{self, Mode, Scheme};
+use xpic::sidecar;
+use xpic::span::Monitor;
+use xpic::spotlight::online::{self, Orientation};
+use xpic::template::Template;
+use xpic::thumbnail;
+use xpic::tone::Tone;
+use xpic::util::Report;
+use xpic::verify;
 use xpic::{bing, spotlight};
 
 #[derive(Parser)]
 #[command(version, about, arg_required_else_help(true))]
-struct CLI {
+struct Cli {
+    /// The configuration file
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
     #[command(subcommand)]
     command: Commands,
 }
 
-impl CLI {
+impl Cli {
     async fn run(self) {
-        self.command.run().await;
+        let config = match self.config {
+            Some(path) => match Config::load(path) {
+                Ok(config) => config,
+                Err(err) => {
+                    eprintln!("failed to load config: {err}");
+                    return;
+                }
+            },
+            None => Config::default(),
+        };
+
+        self.command.run(config).await;
+    }
+}
+
+fn print_report(report: &Report) {
+    for dropped in &report.dropped {
+        println!(
+            "dropped {} (duplicate of {})",
+            dropped.path.display(),
+            dropped.kept.display()
+        );
+    }
+
+    println!(
+        "{} new, {} already present, {} failed, {} duplicates dropped",
+        report.new,
+        report.existing,
+        report.failed,
+        report.dropped.len()
+    );
+}
+
+/// Options of saving wallpapers that override the configuration file.
+#[derive(clap::Args)]
+struct SaveArgs {
+    /// Write metadata files next to each wallpaper (json, xmp)
+    #[arg(long = "sidecar", value_delimiter = ',')]
+    sidecars: Vec<sidecar::Format>,
+    /// Write credits into the EXIF, XMP and IPTC fields of each wallpaper
+    #[arg(long)]
+    embed: bool,
+    /// Name saved files after a template, e.g. "{date}_{title}_{market}.{ext}" or "{source}/{hash}.{ext}"
+    #[arg(long)]
+    template: Option<Template>,
+    /// Arrange saved files in directories (flat, source, year-month, market or orientation)
+    #[arg(long)]
+    layout: Option<Layout>,
+    /// Convert saved files to a format (png, webp or avif)
+    #[arg(long)]
+    convert: Option<convert::Format>,
+    /// Quality of lossy WebP and AVIF, from 1 to 100
+    #[arg(long, requires = "convert", value_parser = clap::value_parser!(u8).range(1..=100))]
+    quality: Option<u8>,
+    /// Encode WebP losslessly
+    #[arg(long, requires = "convert")]
+    lossless: bool,
+    /// Keep the original file next to the converted one
+    #[arg(long, requires = "convert")]
+    keep_original: bool,
+    /// Also save a copy resized and cropped to a screen geometry, e.g. 2560x1440; can be repeated
+    #[arg(long, value_parser = |s: &str| parse_dimensions(s).map_err(|e| e.to_string()))]
+    resize: Vec<(u32, u32)>,
+    /// Crop resized copies around the subject rather than the center
+    #[arg(long)]
+    smart_crop: bool,
+    /// Also save a copy with the title and credit rendered onto it
+    #[arg(long)]
+    caption: bool,
+    /// Also save a lock screen variant, e.g. blur2-dim40 or desaturate100; can be repeated
+    #[arg(long = "effect")]
+    effects: Vec<Effect>,
+}
+
+impl SaveArgs {
+    fn apply(self, save: &mut SaveOptions) {
+        if !self.sidecars.is_empty() {
+            save.sidecars = self.sidecars;
+        }
+        save.embed |= self.embed;
+        save.template = self.template.or(save.template.take());
+        save.layout = self.layout.unwrap_or(save.layout);
+
+        if let Some(format) = self.convert {
+            let defaults = Convert::new(format);
+            save.convert = Some(Convert {
+                format,
+                quality: self.quality.unwrap_or(defaults.quality),
+                lossless: self.lossless,
+                keep_original: self.keep_original,
+            });
+        }
+
+        if !self.resize.is_empty() {
+            save.resize = self
+                .resize
+                .into_iter()
+                .map(|(width, height)| Resize::new(width, height))
+                .collect();
+        }
+        if self.smart_crop {
+            for resize in &mut save.resize {
+                resize.smart = true;
+            }
+        }
+        if self.caption && save.caption.is_none() {
+            save.caption = Some(Caption::default());
+        }
+        if !self.effects.is_empty() {
+            save.effects = self.effects;
+        }
+    }
+}
+
+/// Returns the entry of `path`, or all entries if no path is given. Errors are printed.
+fn list_entries(library: &Library, path: Option<&Path>) -> Option<Vec<Entry>> {
+    let entries = match path {
+        Some(path) => library.entry(path).map(|entry| vec![entry]),
+        None => library.list(),
+    };
+
+    entries
+        .map_err(|err| eprintln!("failed to list wallpapers: {err}"))
+        .ok()
+}
+
+/// Counts successes and failures of a command run over many wallpapers.
+#[derive(Default)]
+struct Tally {
+    done: usize,
+    failed: usize,
+}
+
+impl Tally {
+    /// Counts a result, printing the error of a failure.
+    fn record<T>(&mut self, result: Result<T, impl std::fmt::Display>) -> Option<T> {
+        match result {
+            Ok(value) => {
+                self.done += 1;
+                Some(value)
+            }
+            Err(err) => {
+                eprintln!("{err}");
+                self.failed += 1;
+                None
+            }
+        }
+    }
+}
+
+fn print_entries(entries: Vec<Entry>, number: Option<usize>) {
+    for entry in entries.into_iter().take(number.unwrap_or(usize::MAX)) {
+        println!(
+            "{}\t{}\t{}\t{}",
+            entry.path.display(),
+            entry.metadata.source,
+            entry.metadata.title.unwrap_or_default(),
+            entry.tags.join(",")
+        );
     }
 }
 
@@ -23,13 +209,673 @@ enum Commands {
     /// Windows Spotlight wallpapers
     #[command(subcommand)]
     Spotlight(Spotlight),
+    /// Saved wallpapers
+    #[command(subcommand)]
+    Library(LibraryCommand),
+    /// Spread a wallpaper over several monitors
+    Span {
+        /// The wallpaper
+        path: PathBuf,
+        /// A monitor as NAME:WxH+X+Y, optionally with its physical size in millimeters as @WMMxHMM, e.g.
+        /// DP-1:2560x1440+0+0@597x336; can be repeated. Defaults to the configured monitors
+        #[arg(long = "monitor")]
+        monitors: Vec<Monitor>,
+        /// Width of the bezels between adjacent monitors in millimeters
+        #[arg(long)]
+        bezel: Option<f64>,
+        /// The directory written to
+        #[arg(long, default_value = ".")]
+        output: PathBuf,
+        /// Write one image per monitor instead of one image covering all monitors
+        #[arg(long)]
+        split: bool,
+    },
 }
 
 impl Commands {
-    async fn run(self) {
+    async fn run(self, config: Config) {
         match self {
-            Commands::Bing(command) => command.run().await,
-            Commands::Spotlight(command) => command.run(),
+            Commands::Bing(command) => command.run(config).await,
+            Commands::Spotlight(command) => command.run(config).await,
+            Commands::Library(command) => command.run(config).await,
+            Commands::Span {
+                path,
+                monitors,
+                bezel,
+                output,
+                split,
+            } => span(config, path, monitors, bezel, output, split),
+        }
+    }
+}
+
+fn span(config: Config, path: PathBuf, monitors: Vec<Monitor>, bezel: Option<f64>, output: PathBuf, split: bool) {
+    let mut span = config.span.unwrap_or_default();
+    if !monitors.is_empty() {
+        span.monitors = monitors;
+    }
+    span.bezel = bezel.unwrap_or(span.bezel);
+    if span.monitors.is_empty() {
+        eprintln!("no monitors given or configured");
+        return;
+    }
+
+    if split {
+        match span.split_to(&path, &output) {
+            // Monitor names first, for setters that take an image per output, e.g. `swaybg -o NAME -i PATH`.
+            Ok(written) => {
+                for (name, path) in written {
+                    println!("{}\t{}", name, path.display());
+                }
+            }
+            Err(err) => eprintln!("failed to split {}: {}", path.display(), err),
+        }
+    } else {
+        match span.span_to(&path, &output) {
+            Ok(written) => println!("{}", written.display()),
+            Err(err) => eprintln!("failed to span {}: {}", path.display(), err),
+        }
+    }
+}
+
+#[derive(Subcommand)]
+enum LibraryCommand {
+    /// List saved wallpapers, most recent first
+    List {
+        /// The directory where wallpapers are saved
+        dir: PathBuf,
+        /// The number of wallpapers to list
+        #[arg(short)]
+        number: Option<usize>,
+    },
+    /// Search saved wallpapers by title, copyright, description, market or date, best matches first
+    Search {
+        /// The directory where wallpapers are saved
+        dir: PathBuf,
+        /// Words to search for
+        #[arg(default_value = "")]
+        text: String,
+        /// Earliest date, e.g. 2024, 2024-03 or 2024-03-15
+        #[arg(long)]
+        since: Option<String>,
+        /// Latest date, e.g. 2024, 2024-03 or 2024-03-15
+        #[arg(long)]
+        until: Option<String>,
+        /// Only wallpapers with this tag
+        #[arg(long)]
+        tag: Option<String>,
+        /// Only favorites
+        #[arg(long)]
+        favorite: bool,
+        /// Include hidden wallpapers
+        #[arg(long)]
+        hidden: bool,
+        /// Only wallpapers with a dominant color like this, e.g. blue or "#1e5ac8"
+        #[arg(long)]
+        color: Option<Color>,
+        /// Only wallpapers of this tone (dark, light or mixed)
+        #[arg(long)]
+        tone: Option<Tone>,
+        /// The number of wallpapers to list
+        #[arg(short)]
+        number: Option<usize>,
+    },
+    /// Mark a wallpaper as favorite, so that it is never pruned
+    Favorite {
+        /// The directory where wallpapers are saved
+        dir: PathBuf,
+        /// The wallpaper
+        path: PathBuf,
+        /// Unmark the wallpaper instead
+        #[arg(long)]
+        unset: bool,
+    },
+    /// Hide a wallpaper, so that it is not shown even when synced again
+    Hide {
+        /// The directory where wallpapers are saved
+        dir: PathBuf,
+        /// The wallpaper
+        path: PathBuf,
+        /// Show the wallpaper again instead
+        #[arg(long)]
+        unset: bool,
+    },
+    /// Add tags to a wallpaper
+    Tag {
+        /// The directory where wallpapers are saved
+        dir: PathBuf,
+        /// The wallpaper
+        path: PathBuf,
+        /// The tags
+        #[arg(required = true)]
+        tags: Vec<String>,
+        /// Remove the tags instead
+        #[arg(long)]
+        remove: bool,
+    },
+    /// List tags with the number of wallpapers having them
+    Tags {
+        /// The directory where wallpapers are saved
+        dir: PathBuf,
+    },
+    /// Generate missing or outdated thumbnails and remove those of wallpapers no longer saved
+    Thumbnails {
+        /// The directory where wallpapers are saved
+        dir: PathBuf,
+        /// The box thumbnails cover, e.g. 480x270
+        #[arg(long, value_parser = |s: &str| parse_dimensions(s).map_err(|e| e.to_string()))]
+        size: Option<(u32, u32)>,
+        /// The format (jpeg or webp)
+        #[arg(long)]
+        format: Option<thumbnail::Format>,
+    },
+    /// Save copies of wallpapers resized and cropped to screen geometries next to them
+    Resize {
+        /// The directory where wallpapers are saved
+        dir: PathBuf,
+        /// A screen geometry, e.g. 2560x1440; can be repeated. Defaults to the configured ones
+        #[arg(long, value_parser = |s: &str| parse_dimensions(s).map_err(|e| e.to_string()))]
+        size: Vec<(u32, u32)>,
+        /// The point kept in view, as fractions of the width and height, e.g. 0.5,0.3. Defaults to the center
+        #[arg(long, conflicts_with = "smart_crop")]
+        focus: Option<Focus>,
+        /// Keep the subject in view, found by saliency and hints of the source, e.g. for portrait phone screens
+        #[arg(long)]
+        smart_crop: bool,
+    },
+    /// Save copies of wallpapers with their title and credit rendered onto them next to them
+    Caption {
+        /// The directory where wallpapers are saved
+        dir: PathBuf,
+        /// The wallpaper. Defaults to all wallpapers
+        path: Option<PathBuf>,
+        /// TrueType or OpenType font file. Defaults to the configured one or a common system font
+        #[arg(long)]
+        font: Option<PathBuf>,
+        /// Height of the title in percent of the image height
+        #[arg(long)]
+        size: Option<f32>,
+        /// The corner (top-left, top-right, bottom-left or bottom-right)
+        #[arg(long)]
+        corner: Option<Corner>,
+        /// What keeps the text readable (shadow, backdrop or none)
+        #[arg(long)]
+        background: Option<Background>,
+        /// Text color, e.g. white or "#ffcc00". Defaults to white or black, whichever contrasts more
+        #[arg(long)]
+        color: Option<Color>,
+    },
+    /// Write lock screen variants, such as blurred or darkened copies, of wallpapers
+    Effect {
+        /// The directory where wallpapers are saved
+        dir: PathBuf,
+        /// The wallpaper. Defaults to all wallpapers
+        path: Option<PathBuf>,
+        /// Effects with their strength, e.g. blur2-dim40 for a blur of 2% of the height, darkened by 40%, or
+        /// desaturate100; can be repeated. Defaults to the configured effects
+        #[arg(long = "effect")]
+        effects: Vec<Effect>,
+    },
+    /// Print the colors of a wallpaper, or extract missing palettes of all wallpapers
+    Palette {
+        /// The directory where wallpapers are saved
+        dir: PathBuf,
+        /// The wallpaper
+        path: Option<PathBuf>,
+    },
+    /// Print whether a wallpaper is dark, light or mixed, or classify all wallpapers not classified yet
+    Tone {
+        /// The directory where wallpapers are saved
+        dir: PathBuf,
+        /// The wallpaper
+        path: Option<PathBuf>,
+    },
+    /// Print a terminal color scheme derived from a wallpaper
+    Scheme {
+        /// The directory where wallpapers are saved
+        dir: PathBuf,
+        /// The wallpaper. Defaults to the most recently saved one
+        path: Option<PathBuf>,
+        /// The format (json, xresources, kitty, alacritty, foot or css)
+        #[arg(long, default_value = "json")]
+        format: scheme::Format,
+        /// Dark or light background. Defaults to the one that suits the wallpaper
+        #[arg(long)]
+        mode: Option<Mode>,
+    },
+    /// Add wallpapers that are not in the catalog yet
+    Scan {
+        /// The directory where wallpapers are saved
+        dir: PathBuf,
+    },
+    /// Check that saved wallpapers decode and match the catalog, and list the problems found
+    Verify {
+        /// The directory where wallpapers are saved
+        dir: PathBuf,
+        /// Fix extensions and fetch broken wallpapers again where possible
+        #[arg(long)]
+        repair: bool,
+    },
+    /// Move wallpapers into the directories of a layout
+    Reorganize {
+        /// The directory where wallpapers are saved
+        dir: PathBuf,
+        /// The layout (flat, source, year-month, market or orientation)
+        #[arg(long)]
+        layout: Layout,
+        /// List moves without moving anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Remove or move wallpapers according to retention rules and list them
+    Prune {
+        /// The directory where wallpapers are saved
+        dir: PathBuf,
+        /// Keep wallpapers first seen within this many days
+        #[arg(long)]
+        max_age_days: Option<u64>,
+        /// Keep at most this much, newest first, e.g. 5G
+        #[arg(long, value_parser = |s: &str| parse_file_size(s).map_err(|e| e.to_string()))]
+        max_size: Option<u64>,
+        /// Drop wallpapers smaller than this, e.g. 1920x1080
+        #[arg(long, value_parser = |s: &str| parse_dimensions(s).map_err(|e| e.to_string()))]
+        min_size: Option<(u32, u32)>,
+        /// Move wallpapers to this directory instead of deleting them
+        #[arg(long)]
+        move_to: Option<PathBuf>,
+        /// List wallpapers without removing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Link copies of the same picture across sources and list all but the best copy
+    Dedup {
+        /// The directory where wallpapers are saved
+        dir: PathBuf,
+        /// Which copy to keep (resolution, file-size or newest)
+        #[arg(long)]
+        prefer: Option<Preference>,
+        /// Maximum perceptual hash distance of the same picture
+        #[arg(long)]
+        threshold: Option<u32>,
+        /// Delete all but the best copy
+        #[arg(long)]
+        remove: bool,
+    },
+}
+
+impl LibraryCommand {
+    async fn run(self, config: Config) {
+        let dir = match &self {
+            LibraryCommand::List { dir, .. }
+            | LibraryCommand::Search { dir, .. }
+            | LibraryCommand::Favorite { dir, .. }
+            | LibraryCommand::Hide { dir, .. }
+            | LibraryCommand::Tag { dir, .. }
+            | LibraryCommand::Tags { dir }
+            | LibraryCommand::Thumbnails { dir, .. }
+            | LibraryCommand::Resize { dir, .. }
+            | LibraryCommand::Caption { dir, .. }
+            | LibraryCommand::Effect { dir, .. }
+            | LibraryCommand::Palette { dir, .. }
+            | LibraryCommand::Tone { dir, .. }
+            | LibraryCommand::Scheme { dir, .. }
+            | LibraryCommand::Scan { dir }
+            | LibraryCommand::Verify { dir, .. }
+            | LibraryCommand::Reorganize { dir, .. }
+            | LibraryCommand::Prune { dir, .. }
+            | LibraryCommand::Dedup { dir, .. } => dir,
+        };
+
+        let library = match Library::open(dir) {
+            Ok(library) => library,
+            Err(err) => {
+                eprintln!("failed to open library {}: {}", dir.display(), err);
+                return;
+            }
+        };
+
+        match self {
+            LibraryCommand::List { number, .. } => match library.list() {
+                Ok(entries) => print_entries(entries, number),
+                Err(err) => eprintln!("failed to list wallpapers: {err}"),
+            },
+            LibraryCommand::Search {
+                text,
+                since,
+                until,
+                tag,
+                favorite,
+                hidden,
+                color,
+                tone,
+                number,
+                ..
+            } => match library.search(&Search {
+                text,
+                since,
+                until,
+                tag,
+                favorite,
+                hidden,
+                color,
+                tone,
+            }) {
+                Ok(entries) => print_entries(entries, number),
+                Err(err) => eprintln!("failed to search wallpapers: {err}"),
+            },
+            LibraryCommand::Favorite { path, unset, .. } => {
+                if let Err(err) = library
+                    .entry(&path)
+                    .and_then(|entry| library.catalog().set_favorite(entry.id, !unset))
+                {
+                    eprintln!("failed to mark {}: {}", path.display(), err);
+                }
+            }
+            LibraryCommand::Hide { path, unset, .. } => {
+                if let Err(err) = library
+                    .entry(&path)
+                    .and_then(|entry| library.catalog().set_hidden(entry.id, !unset))
+                {
+                    eprintln!("failed to hide {}: {}", path.display(), err);
+                }
+            }
+            LibraryCommand::Tag { path, tags, remove, .. } => {
+                if let Err(err) = library.entry(&path).and_then(|entry| {
+                    tags.iter().try_for_each(|tag| match remove {
+                        true => library.catalog().remove_tag(entry.id, tag),
+                        false => library.catalog().add_tag(entry.id, tag),
+                    })
+                }) {
+                    eprintln!("failed to tag {}: {}", path.display(), err);
+                }
+            }
+            LibraryCommand::Tags { .. } => match library.catalog().tags() {
+                Ok(tags) => {
+                    for (tag, count) in tags {
+                        println!("{tag}\t{count}");
+                    }
+                }
+                Err(err) => eprintln!("failed to list tags: {err}"),
+            },
+            LibraryCommand::Thumbnails { size, format, .. } => {
+                let mut thumbnail = config.thumbnail;
+                if let Some((width, height)) = size {
+                    thumbnail.width = width;
+                    thumbnail.height = height;
+                }
+                if let Some(format) = format {
+                    thumbnail.format = format;
+                }
+
+                let Some(entries) = list_entries(&library, None) else {
+                    return;
+                };
+
+                let mut tally = Tally::default();
+                for entry in &entries {
+                    tally.record(thumbnail.get(&library, entry));
+                }
+
+                match thumbnail::clean(&library) {
+                    Ok(removed) => println!(
+                        "{} thumbnails, {} failed, {} removed",
+                        tally.done, tally.failed, removed
+                    ),
+                    Err(err) => eprintln!("failed to clean thumbnails: {err}"),
+                }
+            }
+            LibraryCommand::Resize {
+                size,
+                focus,
+                smart_crop,
+                ..
+            } => {
+                let mut resizes = if size.is_empty() {
+                    config.save.resize.clone()
+                } else {
+                    size.into_iter()
+                        .map(|(width, height)| Resize::new(width, height))
+                        .collect()
+                };
+                if resizes.is_empty() {
+                    eprintln!("no screen geometry given or configured");
+                    return;
+                }
+                for resize in &mut resizes {
+                    resize.focus = focus.or(resize.focus);
+                    resize.smart |= smart_crop;
+                }
+
+                let Some(entries) = list_entries(&library, None) else {
+                    return;
+                };
+
+                let mut tally = Tally::default();
+                for entry in &entries {
+                    for resize in &resizes {
+                        let result = resize
+                            .resize(&entry.path, entry.metadata.focus)
+                            .and_then(|path| library.add_derived(entry, &path, Kind::Resize).map(|()| path))
+                            .map_err(|err| format!("failed to resize {}: {}", entry.path.display(), err));
+                        if let Some(path) = tally.record(result) {
+                            println!("{}", path.display());
+                        }
+                    }
+                }
+                println!("{} resized, {} failed", tally.done, tally.failed);
+            }
+            LibraryCommand::Caption {
+                path,
+                font,
+                size,
+                corner,
+                background,
+                color,
+                ..
+            } => {
+                let default = config.save.caption.unwrap_or_default();
+                let caption = Caption {
+                    font: font.or(default.font),
+                    size: size.unwrap_or(default.size),
+                    corner: corner.unwrap_or(default.corner),
+                    background: background.unwrap_or(default.background),
+                    color: color.or(default.color),
+                };
+
+                let Some(entries) = list_entries(&library, path.as_deref()) else {
+                    return;
+                };
+
+                let mut tally = Tally::default();
+                for entry in entries.iter().filter(|entry| entry.metadata.has_credits()) {
+                    let result = caption
+                        .caption(&entry.path, &entry.metadata)
+                        .and_then(|path| library.add_derived(entry, &path, Kind::Caption).map(|()| path))
+                        .map_err(|err| format!("failed to caption {}: {}", entry.path.display(), err));
+                    if let Some(path) = tally.record(result) {
+                        println!("{}", path.display());
+                    }
+                }
+                println!("{} captioned, {} failed", tally.done, tally.failed);
+            }
+            LibraryCommand::Effect { path, effects, .. } => {
+                let effects = if effects.is_empty() {
+                    config.save.effects
+                } else {
+                    effects
+                };
+                if effects.is_empty() {
+                    eprintln!("no effect given or configured");
+                    return;
+                }
+
+                let Some(entries) = list_entries(&library, path.as_deref()) else {
+                    return;
+                };
+
+                let mut tally = Tally::default();
+                for entry in &entries {
+                    for effect in &effects {
+                        let result = effect
+                            .derive(&entry.path)
+                            .and_then(|path| library.add_derived(entry, &path, Kind::Effect).map(|()| path))
+                            .map_err(|err| format!("failed to apply {} to {}: {}", effect, entry.path.display(), err));
+                        if let Some(path) = tally.record(result) {
+                            println!("{}", path.display());
+                        }
+                    }
+                }
+                println!("{} written, {} failed", tally.done, tally.failed);
+            }
+            LibraryCommand::Palette { path: Some(path), .. } => {
+                match library.entry(&path).and_then(|entry| library.palette(&entry)) {
+                    Ok(palette) => {
+                        for swatch in &palette.dominant {
+                            println!("dominant\t{}\t{:.0}%", swatch.color, swatch.share * 100.0);
+                        }
+                        println!("average\t{}", palette.average);
+                        println!("accent\t{}", palette.accent);
+                        println!("contrast\t{}", palette.contrast);
+                    }
+                    Err(err) => eprintln!("failed to get palette of {}: {}", path.display(), err),
+                }
+            }
+            LibraryCommand::Palette { path: None, .. } => {
+                let Some(entries) = list_entries(&library, None) else {
+                    return;
+                };
+
+                let mut tally = Tally::default();
+                for entry in entries.iter().filter(|entry| entry.metadata.palette.is_none()) {
+                    tally.record(
+                        library
+                            .palette(entry)
+                            .map_err(|err| format!("failed to extract palette of {}: {}", entry.path.display(), err)),
+                    );
+                }
+                println!("{} extracted, {} failed", tally.done, tally.failed);
+            }
+            LibraryCommand::Tone { path: Some(path), .. } => {
+                match library.entry(&path).and_then(|entry| library.tone(&entry)) {
+                    Ok(tone) => println!("{tone}"),
+                    Err(err) => eprintln!("failed to get tone of {}: {}", path.display(), err),
+                }
+            }
+            LibraryCommand::Tone { path: None, .. } => {
+                let Some(entries) = list_entries(&library, None) else {
+                    return;
+                };
+
+                let mut tally = Tally::default();
+                for entry in entries.iter().filter(|entry| entry.metadata.tone.is_none()) {
+                    tally.record(
+                        library
+                            .tone(entry)
+                            .map_err(|err| format!("failed to measure tone of {}: {}", entry.path.display(), err)),
+                    );
+                }
+                println!("{} classified, {} failed", tally.done, tally.failed);
+            }
+            LibraryCommand::Scheme { path, format, mode, .. } => {
+                let entry = match path {
+                    Some(path) => library.entry(&path),
+                    None => library
+                        .list()
+                        .and_then(|entries| entries.into_iter().next().ok_or("no wallpapers".into())),
+                };
+                let scheme = entry.and_then(|entry| {
+                    let palette = library.palette(&entry)?;
+                    let mode = match mode {
+                        Some(mode) => mode,
+                        None => library.tone(&entry)?.into(),
+                    };
+                    Ok(Scheme::of(&palette, mode))
+                });
+
+                match scheme {
+                    Ok(scheme) => print!("{}", scheme.render(format)),
+                    Err(err) => eprintln!("failed to derive color scheme: {err}"),
+                }
+            }
+            LibraryCommand::Scan { .. } => match library.scan() {
+                Ok(added) => println!("{added} added"),
+                Err(err) => eprintln!("failed to scan library: {err}"),
+            },
+            LibraryCommand::Verify { repair, .. } => match verify::verify(&library, repair, &config.save).await {
+                Ok(issues) => {
+                    for issue in &issues {
+                        let status = if issue.repaired { "repaired" } else { "not repaired" };
+                        println!("{}\t{}\t{}", issue.path.display(), issue.problem, status);
+                    }
+                    println!(
+                        "{} problems, {} repaired",
+                        issues.len(),
+                        issues.iter().filter(|issue| issue.repaired).count()
+                    );
+                }
+                Err(err) => eprintln!("failed to verify library: {err}"),
+            },
+            LibraryCommand::Prune {
+                max_age_days,
+                max_size,
+                min_size,
+                move_to,
+                dry_run,
+                ..
+            } => {
+                let default = config.retention.unwrap_or_default();
+                let retention = Retention {
+                    max_age_days: max_age_days.or(default.max_age_days),
+                    max_size: max_size.or(default.max_size),
+                    min_width: min_size.map(|(width, _)| width).or(default.min_width),
+                    min_height: min_size.map(|(_, height)| height).or(default.min_height),
+                    ..default
+                };
+
+                match retention::prune(&library, &retention, move_to.as_deref(), dry_run) {
+                    Ok(pruned) => {
+                        for pruned in &pruned {
+                            println!("{}\t{:?}", pruned.path.display(), pruned.reason);
+                        }
+                        println!(
+                            "{} wallpapers, {} bytes {}",
+                            pruned.len(),
+                            pruned.iter().map(|pruned| pruned.file_size).sum::<u64>(),
+                            if dry_run { "would be pruned" } else { "pruned" }
+                        );
+                    }
+                    Err(err) => eprintln!("failed to prune library: {err}"),
+                }
+            }
+            LibraryCommand::Reorganize { layout, dry_run, .. } => match library.reorganize(layout, dry_run) {
+                Ok(moves) => {
+                    for (from, to) in moves {
+                        println!("{}\t{}", from.display(), to.display());
+                    }
+                }
+                Err(err) => eprintln!("failed to reorganize library: {err}"),
+            },
+            LibraryCommand::Dedup {
+                prefer,
+                threshold,
+                remove,
+                ..
+            } => {
+                let default = config.dedup.unwrap_or_default();
+                let dedup = Dedup {
+                    prefer: prefer.unwrap_or(default.prefer),
+                    threshold: threshold.unwrap_or(default.threshold),
+                };
+
+                match dedup::dedup_library(&library, dedup, remove) {
+                    Ok(dropped) => {
+                        for dropped in dropped {
+                            println!("{}\t{}", dropped.path.display(), dropped.kept.display());
+                        }
+                    }
+                    Err(err) => eprintln!("failed to deduplicate library: {err}"),
+                }
+            }
         }
     }
 }
@@ -41,24 +887,36 @@ enum Bing {
         /// The number of wallpapers to list
         #[arg(short)]
         number: Option<usize>,
+        /// Only list wallpapers matching the filter, e.g. "min-size=1920x1080 & !market=ROW"
+        #[arg(long)]
+        filter: Option<Filter>,
     },
     /// Save wallpapers to a directory
     Save {
         /// The directory where wallpapers are saved
         dir: PathBuf,
+        /// Only save wallpapers matching the filter, e.g. "min-size=1920x1080 & !market=ROW"
+        #[arg(long)]
+        filter: Option<Filter>,
+        #[command(flatten)]
+        save: SaveArgs,
     },
 }
 
 impl Bing {
-    async fn run(self) {
+    async fn run(self, mut config: Config) {
         match self {
-            Bing::List { number } => Self::list(number).await,
-            Bing::Save { dir } => Self::save(dir).await,
+            Bing::List { number, filter } => Self::list(number, &filter.or(config.filter).unwrap_or_default()).await,
+            Bing::Save { dir, filter, save } => {
+                config.filter = filter.or(config.filter);
+                save.apply(&mut config.save);
+                Self::save(dir, &config).await
+            }
         }
     }
 
-    async fn list(number: Option<usize>) {
-        match bing::get_images().await {
+    async fn list(number: Option<usize>, filter: &Filter) {
+        match bing::get_images(filter).await {
             Ok(images) => {
                 if let Some(number) = number {
                     for url in images.into_iter().take(number) {
@@ -74,13 +932,41 @@ impl Bing {
         }
     }
 
-    async fn save(dir: impl AsRef<Path>) {
-        if let Err(err) = bing::copy_images_to(&dir).await {
-            eprintln!(
-                "failed to copy Bing wallpapers to {}:{}",
-                dir.as_ref().display(),
-                err
-            );
+    async fn save(dir: impl AsRef<Path>, config: &Config) {
+        match bing::copy_images_to(&dir, config.filter.as_ref(), config.dedup, &config.save).await {
+            Ok(report) => {
+                print_report(&report);
+                if !config.schemes.is_empty() {
+                    write_schemes(dir.as_ref(), config);
+                }
+            }
+            Err(err) => eprintln!("failed to copy Bing wallpapers to {}:{}", dir.as_ref().display(), err),
+        }
+    }
+}
+
+/// Writes the configured color schemes of the newest Bing wallpaper in the library.
+fn write_schemes(dir: &Path, config: &Config) {
+    let newest = Library::open(dir).and_then(|library| {
+        let entry = library
+            .list()?
+            .into_iter()
+            .filter(|entry| entry.metadata.source == Source::Bing && !entry.hidden)
+            .max_by(|a, b| a.metadata.date.cmp(&b.metadata.date))
+            .ok_or("no Bing wallpapers")?;
+        Ok((library.palette(&entry)?, library.tone(&entry)?))
+    });
+    let (palette, tone) = match newest {
+        Ok(newest) => newest,
+        Err(err) => {
+            eprintln!("failed to derive color schemes: {err}");
+            return;
+        }
+    };
+
+    for export in &config.schemes {
+        if let Err(err) = export.write(&palette, tone) {
+            eprintln!("failed to write {} color scheme: {}", export.format, err);
         }
     }
 }
@@ -92,24 +978,87 @@ enum Spotlight {
         /// The number of wallpapers to list
         #[arg(short)]
         number: Option<usize>,
+        /// Only list wallpapers matching the filter [default: min-size=1920x1080]
+        #[arg(long)]
+        filter: Option<Filter>,
     },
     /// Save wallpapers to a directory
     Save {
         /// The directory where wallpapers are saved
         dir: PathBuf,
+        /// Only save wallpapers matching the filter [default: min-size=1920x1080]
+        #[arg(long)]
+        filter: Option<Filter>,
+        /// Keep only the best copy of visually identical wallpapers (resolution, file-size or newest)
+        #[arg(long, num_args = 0..=1, default_missing_value = "resolution")]
+        dedup: Option<Preference>,
+        #[command(flatten)]
+        save: SaveArgs,
+    },
+    /// List visually identical wallpapers that would be dropped
+    Duplicates {
+        /// Which copy to keep (resolution, file-size or newest)
+        #[arg(long, default_value_t = Preference::Resolution)]
+        prefer: Preference,
+        /// Only check wallpapers matching the filter [default: min-size=1920x1080]
+        #[arg(long)]
+        filter: Option<Filter>,
+    },
+    /// Watch for new wallpapers and save them to a directory
+    Watch {
+        /// The directory where wallpapers are saved
+        dir: PathBuf,
+        /// Only save wallpapers matching the filter [default: min-size=1920x1080]
+        #[arg(long)]
+        filter: Option<Filter>,
+        /// Seconds to wait for an asset to settle before it is checked
+        #[arg(long, default_value_t = spotlight::watch::DEFAULT_DEBOUNCE.as_secs())]
+        debounce: u64,
     },
+    /// Windows Spotlight wallpapers from the content API
+    #[command(subcommand)]
+    Online(Online),
 }
 
 impl Spotlight {
-    fn run(self) {
+    async fn run(self, mut config: Config) {
         match self {
-            Spotlight::List { number } => Self::list(number),
-            Spotlight::Save { dir } => Self::save(dir),
+            Spotlight::List { number, filter } => {
+                Self::list(number, &spotlight::asset_filter(filter.or(config.filter).as_ref()))
+            }
+            Spotlight::Save {
+                dir,
+                filter,
+                dedup,
+                save,
+            } => {
+                config.filter = filter.or(config.filter);
+                save.apply(&mut config.save);
+                if let Some(prefer) = dedup {
+                    config.dedup = Some(Dedup {
+                        prefer,
+                        ..config.dedup.unwrap_or_default()
+                    });
+                }
+                Self::save(dir, &config)
+            }
+            Spotlight::Duplicates { prefer, filter } => Self::duplicates(
+                Dedup {
+                    prefer,
+                    ..config.dedup.unwrap_or_default()
+                },
+                &spotlight::asset_filter(filter.or(config.filter).as_ref()),
+            ),
+            Spotlight::Watch { dir, filter, debounce } => {
+                config.filter = filter.or(config.filter);
+                Self::watch(dir, &config, debounce).await
+            }
+            Spotlight::Online(command) => command.run(config).await,
         }
     }
 
-    fn list(number: Option<usize>) {
-        match spotlight::get_images() {
+    fn list(number: Option<usize>, filter: &Filter) {
+        match spotlight::get_images(filter) {
             Ok(images) => {
                 if let Some(number) = number {
                     for path in images.into_iter().take(number) {
@@ -125,18 +1074,128 @@ impl Spotlight {
         }
     }
 
-    fn save(dir: impl AsRef<Path>) {
-        if let Err(err) = spotlight::copy_images_to(&dir) {
-            eprintln!(
+    fn save(dir: impl AsRef<Path>, config: &Config) {
+        match spotlight::copy_images_to(&dir, config.filter.as_ref(), config.dedup, &config.save) {
+            Ok(report) => print_report(&report),
+            Err(err) => eprintln!(
+                "failed to copy Windows Spotlight wallpapers to {}:{}",
+                dir.as_ref().display(),
+                err
+            ),
+        }
+    }
+
+    fn duplicates(dedup: Dedup, filter: &Filter) {
+        match spotlight::get_images(filter) {
+            Ok(images) => {
+                for dropped in spotlight::dedup_images(images, dedup).1 {
+                    println!("{}\t{}", dropped.path.display(), dropped.kept.display());
+                }
+            }
+            Err(err) => eprintln!("failed to get Windows Spotlight wallpapers: {err}"),
+        }
+    }
+
+    async fn watch(dir: impl AsRef<Path>, config: &Config, debounce: u64) {
+        // Copy the current assets first, then keep up with new ones.
+        Self::save(&dir, config);
+
+        let _watcher = match spotlight::watch::watch(
+            &dir,
+            config.filter.as_ref(),
+            config.save.clone(),
+            Duration::from_secs(debounce),
+            |path| println!("{}", path.display()),
+        ) {
+            Ok(watcher) => watcher,
+            Err(err) => {
+                eprintln!("failed to watch Windows Spotlight wallpapers: {err}");
+                return;
+            }
+        };
+
+        if let Err(err) = tokio::signal::ctrl_c().await {
+            eprintln!("failed to wait for Ctrl-C: {err}");
+        }
+    }
+}
+
+#[derive(clap::Args)]
+struct OnlineArgs {
+    /// The locale of wallpapers
+    #[arg(long, default_value = "en-US")]
+    locale: String,
+    /// The orientation of wallpapers (landscape or portrait)
+    #[arg(long, default_value_t = Orientation::Landscape)]
+    orientation: Orientation,
+    /// The content API endpoint
+    #[arg(long, default_value = online::DEFAULT_ENDPOINT)]
+    endpoint: Url,
+}
+
+#[derive(Subcommand)]
+enum Online {
+    /// List Windows Spotlight wallpapers
+    List {
+        /// The number of wallpapers to list
+        #[arg(short)]
+        number: Option<usize>,
+        #[command(flatten)]
+        args: OnlineArgs,
+    },
+    /// Save wallpapers to a directory
+    Save {
+        /// The directory where wallpapers are saved
+        dir: PathBuf,
+        #[command(flatten)]
+        args: OnlineArgs,
+        #[command(flatten)]
+        save: SaveArgs,
+    },
+}
+
+impl Online {
+    async fn run(self, mut config: Config) {
+        match self {
+            Online::List { number, args } => Self::list(number, args).await,
+            Online::Save { dir, args, save } => {
+                save.apply(&mut config.save);
+                Self::save(dir, args, &config).await
+            }
+        }
+    }
+
+    async fn list(number: Option<usize>, args: OnlineArgs) {
+        let client = online::Client::new(args.endpoint);
+
+        match client.get_images(&args.locale, args.orientation).await {
+            Ok(images) => {
+                for image in images.into_iter().take(number.unwrap_or(usize::MAX)) {
+                    println!("{}\t{}\t{}", image.url, image.title, image.copyright);
+                }
+            }
+            Err(err) => eprintln!("failed to get Windows Spotlight wallpapers: {err}"),
+        }
+    }
+
+    async fn save(dir: impl AsRef<Path>, args: OnlineArgs, config: &Config) {
+        let client = online::Client::new(args.endpoint);
+
+        match client
+            .copy_images_to(&dir, &args.locale, args.orientation, &config.save)
+            .await
+        {
+            Ok(report) => print_report(&report),
+            Err(err) => eprintln!(
                 "failed to copy Windows Spotlight wallpapers to {}:{}",
                 dir.as_ref().display(),
                 err
-            );
+            ),
         }
     }
 }
 
 #[tokio::main]
 async fn main() {
-    CLI::parse().run().await;
+    Cli::parse().run().await;
 }
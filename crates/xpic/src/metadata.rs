@@ -0,0 +1,159 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::palette::Palette;
+use crate::resize::Focus;
+use crate::tone::Tone;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Source {
+    Bing,
+    Spotlight,
+}
+
+impl FromStr for Source {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "bing" => Ok(Source::Bing),
+            "spotlight" => Ok(Source::Spotlight),
+            _ => Err(format!("unknown source: {s}")),
+        }
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Bing => write!(f, "bing"),
+            Source::Spotlight => write!(f, "spotlight"),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Orientation {
+    #[default]
+    Landscape,
+    Portrait,
+}
+
+impl Orientation {
+    /// Returns orientation of the specified dimensions. Square images are landscape.
+    pub fn of(width: u32, height: u32) -> Self {
+        if height > width {
+            Orientation::Portrait
+        } else {
+            Orientation::Landscape
+        }
+    }
+}
+
+impl FromStr for Orientation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "landscape" => Ok(Orientation::Landscape),
+            "portrait" => Ok(Orientation::Portrait),
+            _ => Err(format!("unknown orientation: {s}")),
+        }
+    }
+}
+
+impl fmt::Display for Orientation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Orientation::Landscape => write!(f, "landscape"),
+            Orientation::Portrait => write!(f, "portrait"),
+        }
+    }
+}
+
+/// Converts a Bing date such as `20240131` to `2024-01-31`. Other dates are returned as is.
+pub fn iso_date(date: &str) -> String {
+    if date.len() == 8 && date.bytes().all(|b| b.is_ascii_digit()) {
+        format!("{}-{}-{}", &date[..4], &date[4..6], &date[6..])
+    } else {
+        date.to_string()
+    }
+}
+
+/// What is known about a wallpaper. Fields a source does not provide are `None`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Metadata {
+    pub source: Source,
+    pub id: String,
+    pub title: Option<String>,
+    pub copyright: Option<String>,
+    /// Longer text about the picture, such as the Bing headline.
+    pub description: Option<String>,
+    pub date: Option<String>,
+    pub market: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub file_size: Option<u64>,
+    /// Bing `wp` flag, whether the image is offered as a wallpaper.
+    pub wallpaper: Option<bool>,
+    /// Bing `drk` hint.
+    pub dark: Option<bool>,
+    /// Page with more about the picture.
+    pub link: Option<String>,
+    /// Where the image was downloaded from.
+    pub url: Option<String>,
+    /// Where the subject of the picture is, from hints of the source.
+    pub focus: Option<Focus>,
+    /// Whether the picture is dark, light or mixed, from a hint of the source or measured.
+    pub tone: Option<Tone>,
+    /// Colors of the picture, measured.
+    pub palette: Option<Palette>,
+}
+
+impl Metadata {
+    pub fn new(source: Source, id: impl Into<String>) -> Self {
+        Self {
+            source,
+            id: id.into(),
+            title: None,
+            copyright: None,
+            description: None,
+            date: None,
+            market: None,
+            width: None,
+            height: None,
+            file_size: None,
+            wallpaper: None,
+            dark: None,
+            link: None,
+            url: None,
+            focus: None,
+            tone: None,
+            palette: None,
+        }
+    }
+
+    pub fn dimensions(&self) -> Option<(u32, u32)> {
+        Some((self.width?, self.height?))
+    }
+
+    /// Returns whether the title or copyright is known.
+    pub fn has_credits(&self) -> bool {
+        self.title.is_some() || self.copyright.is_some()
+    }
+
+    /// Returns the copyright without the leading copyright sign, e.g. `Getty Images` for `© Getty Images`.
+    pub fn copyright_holder(&self) -> Option<&str> {
+        self.copyright
+            .as_deref()
+            .map(|copyright| copyright.trim_start_matches('©').trim())
+            .filter(|holder| !holder.is_empty())
+    }
+
+    pub fn orientation(&self) -> Option<Orientation> {
+        self.dimensions().map(|(width, height)| Orientation::of(width, height))
+    }
+}
@@ -0,0 +1,328 @@
+use image::DynamicImage;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::util;
+
+/// Number of dominant colors.
+const COLORS: usize = 5;
+
+/// Iterations of k-means at most.
+const ITERATIONS: usize = 16;
+
+/// Smallest share of an image a dominant color must cover to match a color search.
+const MIN_SHARE: f64 = 0.1;
+
+/// Largest distance between colors that still look alike.
+pub const MATCH_DISTANCE: f64 = 30.0;
+
+/// Contrast ratio of readable text.
+const READABLE_CONTRAST: f64 = 4.5;
+
+/// An sRGB color, written as `#rrggbb`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub struct Color(pub [u8; 3]);
+
+impl Color {
+    pub const BLACK: Color = Color([0, 0, 0]);
+    pub const WHITE: Color = Color([255, 255, 255]);
+
+    /// Returns the perceived difference to another color in CIELAB, counting lightness half, so that shades of a
+    /// color are close to it.
+    pub fn distance(self, other: Color) -> f64 {
+        let ([l1, a1, b1], [l2, a2, b2]) = (self.lab(), other.lab());
+        (((l1 - l2) / 2.0).powi(2) + (a1 - a2).powi(2) + (b1 - b2).powi(2)).sqrt()
+    }
+
+    fn linear(self) -> [f64; 3] {
+        self.0.map(|c| {
+            let c = c as f64 / 255.0;
+            if c <= 0.04045 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            }
+        })
+    }
+
+    /// Returns the CIELAB coordinates under D65.
+    fn lab(self) -> [f64; 3] {
+        let [r, g, b] = self.linear();
+        let xyz = [
+            (0.4124 * r + 0.3576 * g + 0.1805 * b) / 0.95047,
+            0.2126 * r + 0.7152 * g + 0.0722 * b,
+            (0.0193 * r + 0.1192 * g + 0.9505 * b) / 1.08883,
+        ];
+        let [x, y, z] = xyz.map(|t| {
+            if t > 0.008856 {
+                t.cbrt()
+            } else {
+                7.787 * t + 16.0 / 116.0
+            }
+        });
+
+        [116.0 * y - 16.0, 500.0 * (x - y), 200.0 * (y - z)]
+    }
+
+    /// Returns the WCAG relative luminance, from 0 to 1.
+    pub fn luminance(self) -> f64 {
+        let [r, g, b] = self.linear();
+        0.2126 * r + 0.7152 * g + 0.0722 * b
+    }
+
+    /// Returns the WCAG contrast ratio to another color, from 1 to 21.
+    pub fn contrast(self, other: Color) -> f64 {
+        let (a, b) = (self.luminance(), other.luminance());
+        (a.max(b) + 0.05) / (a.min(b) + 0.05)
+    }
+
+    /// Returns how colorful the color is, from 0 for greys to 255.
+    pub fn chroma(self) -> u8 {
+        self.0.iter().max().unwrap() - self.0.iter().min().unwrap()
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [r, g, b] = self.0;
+        write!(f, "#{r:02x}{g:02x}{b:02x}")
+    }
+}
+
+impl FromStr for Color {
+    type Err = String;
+
+    /// Parses `#rrggbb`, `rrggbb` or a basic color name such as `blue`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let named = match s.to_ascii_lowercase().as_str() {
+            "black" => Some([0, 0, 0]),
+            "white" => Some([255, 255, 255]),
+            "gray" | "grey" => Some([128, 128, 128]),
+            "red" => Some([200, 30, 30]),
+            "orange" => Some([240, 140, 20]),
+            "yellow" => Some([240, 220, 40]),
+            "green" => Some([50, 150, 50]),
+            "cyan" | "turquoise" => Some([40, 190, 200]),
+            "blue" => Some([30, 90, 200]),
+            "purple" => Some([130, 60, 170]),
+            "pink" => Some([240, 130, 180]),
+            "brown" => Some([120, 80, 40]),
+            _ => None,
+        };
+        if let Some(rgb) = named {
+            return Ok(Color(rgb));
+        }
+
+        let hex = s.strip_prefix('#').unwrap_or(s);
+        if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(format!("invalid color: {s}, expected #rrggbb or a color name"));
+        }
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).unwrap();
+
+        Ok(Color([channel(0), channel(2), channel(4)]))
+    }
+}
+
+impl From<Color> for String {
+    fn from(color: Color) -> Self {
+        color.to_string()
+    }
+}
+
+impl TryFrom<String> for Color {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// A dominant color and the share of the image it covers.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Swatch {
+    pub color: Color,
+    /// From 0 to 1.
+    pub share: f64,
+}
+
+/// Colors of an image.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Palette {
+    /// Dominant colors, most common first.
+    pub dominant: Vec<Swatch>,
+    /// Mean of all pixels.
+    pub average: Color,
+    /// The most colorful of the dominant colors that is not too rare, for highlights.
+    pub accent: Color,
+    /// A dominant color readable on the average color, or else black or white.
+    pub contrast: Color,
+}
+
+impl Palette {
+    /// Returns the palette of an image, clustering the colors of a downscaled copy with k-means.
+    pub fn of(img: &DynamicImage) -> Self {
+        let pixels = img
+            .thumbnail(64, 64)
+            .to_rgb8()
+            .pixels()
+            .map(|pixel| pixel.0.map(f64::from))
+            .collect::<Vec<_>>();
+        if pixels.is_empty() {
+            return Self {
+                dominant: Vec::new(),
+                average: Color::BLACK,
+                accent: Color::BLACK,
+                contrast: Color::WHITE,
+            };
+        }
+
+        let average = mean(pixels.iter());
+
+        // Start from the mean and add the pixels farthest from the centers so far, which is deterministic and
+        // spreads the centers over distinct colors.
+        let mut centers = vec![average];
+        while centers.len() < COLORS.min(pixels.len()) {
+            let farthest = pixels
+                .iter()
+                .max_by(|a, b| nearest(&centers, a).1.total_cmp(&nearest(&centers, b).1))
+                .unwrap();
+            centers.push(*farthest);
+        }
+
+        let mut assignments = vec![0; pixels.len()];
+        for _ in 0..ITERATIONS {
+            let mut changed = false;
+            for (pixel, assignment) in pixels.iter().zip(&mut assignments) {
+                let (center, _) = nearest(&centers, pixel);
+                changed |= *assignment != center;
+                *assignment = center;
+            }
+
+            for (i, center) in centers.iter_mut().enumerate() {
+                let members = pixels.iter().zip(&assignments).filter(|(_, a)| **a == i);
+                if members.clone().next().is_some() {
+                    *center = mean(members.map(|(pixel, _)| pixel));
+                }
+            }
+
+            if !changed {
+                break;
+            }
+        }
+
+        let mut dominant = centers
+            .iter()
+            .enumerate()
+            .map(|(i, center)| Swatch {
+                color: to_color(*center),
+                share: assignments.iter().filter(|a| **a == i).count() as f64 / pixels.len() as f64,
+            })
+            .filter(|swatch| swatch.share > 0.0)
+            .collect::<Vec<_>>();
+        dominant.sort_by(|a, b| b.share.total_cmp(&a.share));
+
+        let average = to_color(average);
+        let accent = dominant
+            .iter()
+            .max_by(|a, b| {
+                let score = |swatch: &Swatch| swatch.color.chroma() as f64 * swatch.share.sqrt();
+                score(a).total_cmp(&score(b))
+            })
+            .map_or(average, |swatch| swatch.color);
+        let contrast = dominant
+            .iter()
+            .map(|swatch| swatch.color)
+            .max_by(|a, b| a.contrast(average).total_cmp(&b.contrast(average)))
+            .filter(|color| color.contrast(average) >= READABLE_CONTRAST)
+            .unwrap_or(if Color::WHITE.contrast(average) >= Color::BLACK.contrast(average) {
+                Color::WHITE
+            } else {
+                Color::BLACK
+            });
+
+        Self {
+            dominant,
+            average,
+            accent,
+            contrast,
+        }
+    }
+
+    /// Returns how far the closest dominant color covering a fair share of the image is from `color`.
+    pub fn distance(&self, color: Color) -> f64 {
+        self.dominant
+            .iter()
+            .filter(|swatch| swatch.share >= MIN_SHARE)
+            .map(|swatch| swatch.color.distance(color))
+            .fold(f64::INFINITY, f64::min)
+    }
+
+    /// Returns whether the image looks like it has the color.
+    pub fn matches(&self, color: Color) -> bool {
+        self.distance(color) <= MATCH_DISTANCE
+    }
+}
+
+fn mean<'a>(pixels: impl Iterator<Item = &'a [f64; 3]>) -> [f64; 3] {
+    let (mut sum, mut n) = ([0.0; 3], 0.0);
+    for pixel in pixels {
+        for (sum, channel) in sum.iter_mut().zip(pixel) {
+            *sum += channel;
+        }
+        n += 1.0;
+    }
+    sum.map(|sum| sum / n)
+}
+
+/// Returns the index of the center nearest to `pixel` and the squared distance to it.
+fn nearest(centers: &[[f64; 3]], pixel: &[f64; 3]) -> (usize, f64) {
+    centers
+        .iter()
+        .map(|center| center.iter().zip(pixel).map(|(a, b)| (a - b) * (a - b)).sum::<f64>())
+        .enumerate()
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .unwrap()
+}
+
+fn to_color(rgb: [f64; 3]) -> Color {
+    Color(rgb.map(|channel| channel.round().clamp(0.0, 255.0) as u8))
+}
+
+/// Returns the palette of an image file.
+pub fn palette(path: impl AsRef<Path>) -> Result<Palette, Box<dyn Error>> {
+    Ok(Palette::of(&util::open_image(path)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    #[test]
+    fn test_palette() {
+        // Three quarters dark blue, one quarter orange.
+        let img = DynamicImage::ImageRgb8(RgbImage::from_fn(200, 100, |x, _| {
+            if x < 150 {
+                Rgb([10, 30, 90])
+            } else {
+                Rgb([250, 140, 20])
+            }
+        }));
+
+        let palette = Palette::of(&img);
+        assert_eq!(palette.dominant[0].color, Color([10, 30, 90]));
+        assert!((palette.dominant[0].share - 0.75).abs() < 0.02);
+        assert_eq!(palette.dominant[1].color, Color([250, 140, 20]));
+        assert_eq!(palette.accent, Color([250, 140, 20]));
+        assert!(palette.contrast.contrast(palette.average) >= 3.0);
+
+        assert!(palette.matches("blue".parse().unwrap()));
+        assert!(palette.matches("#f08c14".parse().unwrap()));
+        assert!(!palette.matches("green".parse().unwrap()));
+        assert_eq!(Color([250, 140, 20]).to_string(), "#fa8c14");
+    }
+}
@@ -0,0 +1,77 @@
+use image::DynamicImage;
+use image::imageops::FilterType;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::util;
+
+/// Hashes within this distance are considered the same picture.
+pub const DEFAULT_THRESHOLD: u32 = 6;
+
+/// 64-bit difference hash of an image.
+///
+/// The image is downscaled to 9x8 grayscale and each bit records whether a pixel is brighter
+/// than its right neighbour, so the hash survives resizing and recompression.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub struct PerceptualHash(pub u64);
+
+impl PerceptualHash {
+    pub fn of(img: &DynamicImage) -> Self {
+        let pixels = img.resize_exact(9, 8, FilterType::Triangle).into_luma8();
+
+        let mut hash = 0u64;
+        for y in 0..8 {
+            for x in 0..8 {
+                hash <<= 1;
+                if pixels.get_pixel(x, y)[0] > pixels.get_pixel(x + 1, y)[0] {
+                    hash |= 1;
+                }
+            }
+        }
+
+        PerceptualHash(hash)
+    }
+
+    /// Returns the number of differing bits.
+    pub fn distance(self, other: PerceptualHash) -> u32 {
+        (self.0 ^ other.0).count_ones()
+    }
+}
+
+impl fmt::Display for PerceptualHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+impl FromStr for PerceptualHash {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(PerceptualHash(u64::from_str_radix(s, 16)?))
+    }
+}
+
+impl From<PerceptualHash> for String {
+    fn from(hash: PerceptualHash) -> Self {
+        hash.to_string()
+    }
+}
+
+impl TryFrom<String> for PerceptualHash {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse().map_err(|e: Box<dyn Error>| e.to_string())
+    }
+}
+
+/// Returns perceptual hash of image.
+pub fn hash_image(path: impl AsRef<Path>) -> Result<PerceptualHash, Box<dyn Error>> {
+    // Decode at a reduced size first so the final resize works on few pixels.
+    Ok(PerceptualHash::of(&util::open_image_reduced(path, 64)?))
+}
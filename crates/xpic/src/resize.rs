@@ -0,0 +1,163 @@
+use image::DynamicImage;
+use image::imageops::FilterType;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::derived;
+use crate::saliency;
+use crate::util;
+
+/// A target screen geometry.
+///
+/// ```toml
+/// [[resize]]
+/// width = 3440
+/// height = 1440
+/// # Keep the point at 50% from the left and 30% from the top in view. The center is kept if unset.
+/// focus = { x = 0.5, y = 0.3 }
+///
+/// [[resize]]
+/// width = 1080
+/// height = 2340
+/// # Keep the most interesting part in view instead of the center.
+/// smart = true
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Resize {
+    pub width: u32,
+    pub height: u32,
+    pub focus: Option<Focus>,
+    /// Crop around the subject found by saliency and hints of the source, unless a focus is given.
+    #[serde(default)]
+    pub smart: bool,
+}
+
+/// A point of an image, as fractions of its width and height from the top left corner.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Focus {
+    pub x: f64,
+    pub y: f64,
+}
+
+impl Focus {
+    pub const CENTER: Focus = Focus { x: 0.5, y: 0.5 };
+}
+
+impl FromStr for Focus {
+    type Err = String;
+
+    /// Parses `X,Y`, e.g. `0.5,0.3`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid focus: {s}");
+        let (x, y) = s.split_once(',').ok_or_else(invalid)?;
+        let (x, y) = (x.trim().parse::<f64>(), y.trim().parse::<f64>());
+
+        match (x, y) {
+            (Ok(x), Ok(y)) if (0.0..=1.0).contains(&x) && (0.0..=1.0).contains(&y) => Ok(Focus { x, y }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// Returns the offset of a window of `window` pixels within `size` pixels that centers `focus`, without leaving
+/// the image.
+fn offset(size: u32, window: u32, focus: f64) -> u32 {
+    let center = (size as f64 * focus.clamp(0.0, 1.0)).round() as i64;
+    (center - window as i64 / 2).clamp(0, (size - window) as i64) as u32
+}
+
+impl Resize {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            focus: None,
+            smart: false,
+        }
+    }
+
+    /// Scales an image to cover the target and crops it to exactly the target around the focus. `hint` is where the
+    /// source says the subject is, used by smart cropping.
+    pub fn apply(&self, img: &DynamicImage, hint: Option<Focus>) -> DynamicImage {
+        let focus = match self.focus {
+            Some(focus) => focus,
+            None if self.smart => saliency::focus(img, self.width, self.height, hint),
+            None => Focus::CENTER,
+        };
+
+        let scale = f64::max(
+            self.width as f64 / img.width() as f64,
+            self.height as f64 / img.height() as f64,
+        );
+        let width = ((img.width() as f64 * scale).round() as u32).max(self.width);
+        let height = ((img.height() as f64 * scale).round() as u32).max(self.height);
+        let img = img.resize_exact(width, height, FilterType::Lanczos3);
+
+        img.crop_imm(
+            offset(width, self.width, focus.x),
+            offset(height, self.height, focus.y),
+            self.width,
+            self.height,
+        )
+    }
+
+    /// Returns where the resized image of `path` is saved: next to it, with the target appended to the name, e.g.
+    /// `image_2560x1440.jpg`.
+    pub fn path(&self, path: impl AsRef<Path>) -> PathBuf {
+        derived::path(path, &format!("{}x{}", self.width, self.height))
+    }
+
+    /// Writes the resized image of `path` next to it in the same format, keeping EXIF data and the ICC profile, and
+    /// returns its path. An existing resized image is kept.
+    pub fn resize(&self, path: impl AsRef<Path>, hint: Option<Focus>) -> Result<PathBuf, Box<dyn Error>> {
+        let path = path.as_ref();
+        let dst = self.path(path);
+        if dst.exists() {
+            return Ok(dst);
+        }
+
+        let img = self.apply(&util::open_image(path)?, hint);
+        util::save_derived_image(&img, path, &dst)?;
+
+        Ok(dst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::RgbImage;
+
+    #[test]
+    fn test_resize() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("image.png");
+        // Red on the left half, blue on the right half.
+        RgbImage::from_fn(320, 180, |x, _| {
+            if x < 160 {
+                image::Rgb([255, 0, 0])
+            } else {
+                image::Rgb([0, 0, 255])
+            }
+        })
+        .save(&path)
+        .unwrap();
+
+        let resize = Resize::new(120, 100);
+        let resized = resize.resize(&path, None).unwrap();
+        assert_eq!(resized, dir.path().join("image_120x100.png"));
+        assert_eq!(util::get_image_dimensions(&resized).unwrap(), (120, 100));
+
+        // Focusing on the right keeps only blue.
+        let img = Resize {
+            focus: Some(Focus { x: 1.0, y: 0.5 }),
+            ..Resize::new(60, 90)
+        }
+        .apply(&util::open_image(&path).unwrap(), None)
+        .to_rgb8();
+        assert_eq!(img.dimensions(), (60, 90));
+        assert!(img.pixels().all(|pixel| pixel[2] > 200 && pixel[0] < 50));
+    }
+}
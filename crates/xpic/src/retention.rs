@@ -0,0 +1,243 @@
+use serde::{Deserialize, Deserializer, Serialize};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use crate::catalog::{self, Entry};
+use crate::filter::parse_file_size;
+use crate::library::Library;
+use crate::thumbnail;
+
+/// Rules deciding which wallpapers to keep. Favorites are always kept.
+///
+/// Pruned wallpapers are remembered, so that syncing does not download or copy them again.
+///
+/// ```toml
+/// [retention]
+/// max_age_days = 90
+/// max_size = "5G"
+/// min_width = 1920
+/// min_height = 1080
+/// # Also prune whenever the app updates its wallpapers.
+/// auto = true
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Retention {
+    /// Keep wallpapers first seen within this many days.
+    pub max_age_days: Option<u64>,
+    /// Keep at most this many bytes, newest first. Accepts sizes such as `"5G"`.
+    #[serde(deserialize_with = "deserialize_file_size")]
+    pub max_size: Option<u64>,
+    /// Drop wallpapers narrower than this.
+    pub min_width: Option<u32>,
+    /// Drop wallpapers lower than this.
+    pub min_height: Option<u32>,
+    /// Prune whenever the app updates its wallpapers, rather than only on request.
+    pub auto: bool,
+}
+
+fn deserialize_file_size<'de, D>(d: D) -> Result<Option<u64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Size {
+        Bytes(u64),
+        Text(String),
+    }
+
+    match Option::<Size>::deserialize(d)? {
+        Some(Size::Bytes(bytes)) => Ok(Some(bytes)),
+        Some(Size::Text(text)) => parse_file_size(&text)
+            .map(Some)
+            .map_err(|e| serde::de::Error::custom(e.to_string())),
+        None => Ok(None),
+    }
+}
+
+/// Why a wallpaper is pruned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Reason {
+    /// Below the minimum resolution.
+    Resolution,
+    /// Older than the maximum age.
+    Age,
+    /// Beyond the maximum total size.
+    Size,
+}
+
+/// A wallpaper that is, or would be, pruned.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Pruned {
+    pub path: PathBuf,
+    pub file_size: u64,
+    pub reason: Reason,
+}
+
+impl Retention {
+    fn violates_resolution(&self, entry: &Entry) -> bool {
+        let (width, height) = entry.metadata.dimensions().unwrap_or((u32::MAX, u32::MAX));
+
+        self.min_width.is_some_and(|min| width < min) || self.min_height.is_some_and(|min| height < min)
+    }
+
+    /// Returns entries that the rules drop, given entries ordered from newest to oldest and the current time in
+    /// seconds since the Unix epoch.
+    pub fn plan(&self, entries: &[Entry], now: u64) -> Vec<(usize, Reason)> {
+        let mut pruned = Vec::new();
+        let mut total = 0u64;
+
+        for (i, entry) in entries.iter().enumerate() {
+            let file_size = entry.metadata.file_size.unwrap_or(0);
+
+            if entry.favorite {
+                // Favorites are kept, but still take up space.
+                total += file_size;
+                continue;
+            }
+
+            let reason = if self.violates_resolution(entry) {
+                Some(Reason::Resolution)
+            } else if self
+                .max_age_days
+                .is_some_and(|days| entry.first_seen + days * 86400 < now)
+            {
+                Some(Reason::Age)
+            } else if self.max_size.is_some_and(|max| total + file_size > max) {
+                Some(Reason::Size)
+            } else {
+                None
+            };
+
+            match reason {
+                Some(reason) => pruned.push((i, reason)),
+                None => total += file_size,
+            }
+        }
+
+        pruned
+    }
+}
+
+/// Removes wallpapers of a library that the rules drop, or moves them to `move_to`, and returns them. With `dry_run`
+/// nothing is changed.
+pub fn prune(
+    library: &Library,
+    retention: &Retention,
+    move_to: Option<&Path>,
+    dry_run: bool,
+) -> Result<Vec<Pruned>, Box<dyn Error>> {
+    let entries = library.list()?;
+    let mut pruned = Vec::new();
+
+    for (i, reason) in retention.plan(&entries, catalog::now()) {
+        let entry = &entries[i];
+
+        if !dry_run {
+            match move_to {
+                Some(dir) => {
+                    library.move_to(entry, dir)?;
+                }
+                None => library.remove(entry)?,
+            }
+            library
+                .catalog()
+                .add_pruned(entry.metadata.source, &entry.metadata.id)?;
+        }
+
+        pruned.push(Pruned {
+            path: entry.path.clone(),
+            file_size: entry.metadata.file_size.unwrap_or(0),
+            reason,
+        });
+    }
+
+    if !dry_run && !pruned.is_empty() {
+        thumbnail::clean(library)?;
+    }
+
+    Ok(pruned)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::{Metadata, Source};
+
+    fn entry(id: i64, first_seen: u64, width: u32, favorite: bool) -> Entry {
+        let mut metadata = Metadata::new(Source::Bing, id.to_string());
+        metadata.width = Some(width);
+        metadata.height = Some(width * 9 / 16);
+        metadata.file_size = Some(100);
+
+        Entry {
+            id,
+            metadata,
+            hash: String::new(),
+            path: PathBuf::from(id.to_string()),
+            first_seen,
+            modified: None,
+            phash: None,
+            group_id: None,
+            favorite,
+            hidden: false,
+            tags: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_plan() {
+        let day = 86400;
+        let now = 100 * day;
+        let entries = vec![
+            entry(1, now, 3840, false),
+            entry(2, now - day, 1280, false),
+            entry(3, now - 2 * day, 1920, true),
+            entry(4, now - 3 * day, 1920, false),
+            entry(5, now - 40 * day, 1920, false),
+            entry(6, now - 50 * day, 1920, true),
+        ];
+
+        let retention = Retention {
+            max_age_days: Some(30),
+            max_size: Some(250),
+            min_width: Some(1920),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            retention.plan(&entries, now),
+            vec![(1, Reason::Resolution), (3, Reason::Size), (4, Reason::Age)]
+        );
+        assert!(Retention::default().plan(&entries, now).is_empty());
+
+        let retention: Retention = toml::from_str(r#"max_size = "1K""#).unwrap();
+        assert_eq!(retention.max_size, Some(1024));
+    }
+
+    #[test]
+    fn test_prune() {
+        let dir = tempfile::tempdir().unwrap();
+        let library = Library::open(dir.path()).unwrap();
+        let path = dir.path().join("small.png");
+        image::RgbImage::new(64, 36).save(&path).unwrap();
+        library.add(Metadata::new(Source::Bing, "small"), &path).unwrap();
+
+        let retention = Retention {
+            min_width: Some(1920),
+            ..Default::default()
+        };
+        assert_eq!(prune(&library, &retention, None, true).unwrap().len(), 1);
+        assert!(path.exists());
+
+        let pruned = prune(&library, &retention, None, false).unwrap();
+        assert_eq!(pruned[0].reason, Reason::Resolution);
+        assert!(!path.exists());
+        assert!(library.list().unwrap().is_empty());
+        // Syncing skips it from now on.
+        assert!(library.catalog().is_pruned(Source::Bing, "small").unwrap());
+        assert!(!library.catalog().is_pruned(Source::Spotlight, "small").unwrap());
+    }
+}
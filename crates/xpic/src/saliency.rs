@@ -0,0 +1,221 @@
+use image::{DynamicImage, GrayImage, RgbImage};
+
+use crate::resize::Focus;
+
+/// Longest side of the image saliency is computed on.
+const SIZE: u32 = 160;
+
+/// Side of the blocks local entropy is computed over.
+const BLOCK: u32 = 8;
+
+/// Spread of the preference for the center of the image, as a fraction of its size.
+const CENTER_SIGMA: f64 = 0.5;
+
+/// Spread of the preference for the point given as a hint, as a fraction of the image size.
+const HINT_SIGMA: f64 = 0.2;
+
+/// Returns how interesting each pixel of a downscaled copy of `img` is, row by row, and the size of the copy.
+///
+/// Edges, busy texture and saturated colors make pixels interesting, which tends to find the subject of a photo
+/// without detecting faces: skies, water and blurred backgrounds are smooth and dull, subjects are not. Pixels near
+/// the center and near `hint` are preferred.
+pub fn energy(img: &DynamicImage, hint: Option<Focus>) -> (Vec<f64>, u32, u32) {
+    let small = img.thumbnail(SIZE, SIZE);
+    let (width, height) = (small.width(), small.height());
+    let (gray, rgb) = (small.to_luma8(), small.to_rgb8());
+
+    let edges = normalize(edges(&gray));
+    let entropy = normalize(entropy(&gray));
+    let saturation = saturation(&rgb);
+
+    let mut energy = Vec::with_capacity(edges.len());
+    for y in 0..height {
+        for x in 0..width {
+            let i = (y * width + x) as usize;
+            let (fx, fy) = ((x as f64 + 0.5) / width as f64, (y as f64 + 0.5) / height as f64);
+
+            let mut prior = 0.5 + 0.5 * gaussian(fx, fy, Focus::CENTER, CENTER_SIGMA);
+            if let Some(hint) = hint {
+                prior *= 1.0 + 2.0 * gaussian(fx, fy, hint, HINT_SIGMA);
+            }
+
+            energy.push((0.5 * edges[i] + 0.3 * entropy[i] + 0.2 * saturation[i]) * prior);
+        }
+    }
+
+    (energy, width, height)
+}
+
+/// Returns the focus that keeps the most interesting window with the aspect ratio of `width` x `height` in view.
+pub fn focus(img: &DynamicImage, width: u32, height: u32, hint: Option<Focus>) -> Focus {
+    let (energy, w, h) = energy(img, hint);
+    if w == 0 || h == 0 || width == 0 || height == 0 {
+        return Focus::CENTER;
+    }
+
+    // The window spans the whole image in one direction and slides in the other.
+    let horizontal = w as u64 * height as u64 > h as u64 * width as u64;
+    let (length, window) = if horizontal {
+        (
+            w,
+            ((h as f64 * width as f64 / height as f64).round() as u32).clamp(1, w),
+        )
+    } else {
+        (
+            h,
+            ((w as f64 * height as f64 / width as f64).round() as u32).clamp(1, h),
+        )
+    };
+
+    let mut sums = vec![0.0; length as usize];
+    for y in 0..h {
+        for x in 0..w {
+            sums[if horizontal { x } else { y } as usize] += energy[(y * w + x) as usize];
+        }
+    }
+
+    let mut best = (f64::MIN, 0);
+    let mut sum = sums[..window as usize].iter().sum::<f64>();
+    for offset in 0..=length - window {
+        if offset > 0 {
+            sum += sums[(offset + window - 1) as usize] - sums[(offset - 1) as usize];
+        }
+        if sum > best.0 {
+            best = (sum, offset);
+        }
+    }
+
+    let center = (best.1 as f64 + window as f64 / 2.0) / length as f64;
+    if horizontal {
+        Focus {
+            x: center,
+            y: hint.map_or(0.5, |hint| hint.y),
+        }
+    } else {
+        Focus {
+            x: hint.map_or(0.5, |hint| hint.x),
+            y: center,
+        }
+    }
+}
+
+fn gaussian(x: f64, y: f64, center: Focus, sigma: f64) -> f64 {
+    let d2 = (x - center.x).powi(2) + (y - center.y).powi(2);
+    (-d2 / (2.0 * sigma * sigma)).exp()
+}
+
+fn normalize(mut values: Vec<f64>) -> Vec<f64> {
+    let max = values.iter().cloned().fold(0.0, f64::max);
+    if max > 0.0 {
+        values.iter_mut().for_each(|value| *value /= max);
+    }
+    values
+}
+
+/// Sobel gradient magnitude.
+fn edges(gray: &GrayImage) -> Vec<f64> {
+    let (width, height) = gray.dimensions();
+    let at = |x: i64, y: i64| {
+        let (x, y) = (x.clamp(0, width as i64 - 1), y.clamp(0, height as i64 - 1));
+        gray.get_pixel(x as u32, y as u32)[0] as f64
+    };
+
+    let mut edges = Vec::with_capacity((width * height) as usize);
+    for y in 0..height as i64 {
+        for x in 0..width as i64 {
+            let gx = at(x + 1, y - 1) + 2.0 * at(x + 1, y) + at(x + 1, y + 1)
+                - at(x - 1, y - 1)
+                - 2.0 * at(x - 1, y)
+                - at(x - 1, y + 1);
+            let gy = at(x - 1, y + 1) + 2.0 * at(x, y + 1) + at(x + 1, y + 1)
+                - at(x - 1, y - 1)
+                - 2.0 * at(x, y - 1)
+                - at(x + 1, y - 1);
+            edges.push(gx.hypot(gy));
+        }
+    }
+
+    edges
+}
+
+/// Shannon entropy of the brightness of the block around each pixel.
+fn entropy(gray: &GrayImage) -> Vec<f64> {
+    let (width, height) = gray.dimensions();
+    let mut entropy = vec![0.0; (width * height) as usize];
+
+    for by in (0..height).step_by(BLOCK as usize) {
+        for bx in (0..width).step_by(BLOCK as usize) {
+            let (bw, bh) = (BLOCK.min(width - bx), BLOCK.min(height - by));
+
+            let mut histogram = [0u32; 16];
+            for y in by..by + bh {
+                for x in bx..bx + bw {
+                    histogram[(gray.get_pixel(x, y)[0] >> 4) as usize] += 1;
+                }
+            }
+
+            let total = (bw * bh) as f64;
+            let value = histogram
+                .iter()
+                .filter(|&&count| count > 0)
+                .map(|&count| {
+                    let p = count as f64 / total;
+                    -p * p.log2()
+                })
+                .sum::<f64>();
+
+            for y in by..by + bh {
+                for x in bx..bx + bw {
+                    entropy[(y * width + x) as usize] = value;
+                }
+            }
+        }
+    }
+
+    entropy
+}
+
+/// HSV saturation, from 0 to 1.
+fn saturation(rgb: &RgbImage) -> Vec<f64> {
+    rgb.pixels()
+        .map(|pixel| {
+            let (max, min) = (pixel.0.iter().max().unwrap(), pixel.0.iter().min().unwrap());
+            if *max == 0 {
+                0.0
+            } else {
+                (max - min) as f64 / *max as f64
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgb;
+
+    #[test]
+    fn test_focus() {
+        // A flat grey background with a busy, colorful subject near the right edge.
+        let img = DynamicImage::ImageRgb8(RgbImage::from_fn(640, 360, |x, y| {
+            if (480..600).contains(&x) && (120..260).contains(&y) {
+                Rgb([((x * 37) % 256) as u8, ((y * 59) % 256) as u8, ((x ^ y) % 256) as u8])
+            } else {
+                Rgb([128, 128, 128])
+            }
+        }));
+
+        // A square window keeps the subject.
+        let focus = focus(&img, 1000, 1000, None);
+        assert!(focus.x > 0.6, "{focus:?}");
+        assert_eq!(focus.y, 0.5);
+
+        // Without anything interesting, the hint decides.
+        let flat = DynamicImage::ImageRgb8(RgbImage::from_pixel(640, 360, Rgb([40, 90, 160])));
+        assert_eq!(super::focus(&flat, 1000, 1000, None).x, 0.5);
+        let hint = Focus { x: 0.1, y: 0.3 };
+        let focus = super::focus(&flat, 1000, 1000, Some(hint));
+        assert!(focus.x < 0.4, "{focus:?}");
+        assert_eq!(focus.y, 0.3);
+    }
+}
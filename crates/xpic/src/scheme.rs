@@ -0,0 +1,456 @@
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use crate::palette::{Color, Palette};
+use crate::tone::Tone;
+use crate::util;
+
+/// Hues of red, green, yellow, blue, magenta and cyan, in the order of the ANSI colors.
+const HUES: [f64; 6] = [0.0, 120.0, 55.0, 220.0, 300.0, 185.0];
+
+/// Largest difference in hue at which a color of the wallpaper tints an ANSI color.
+const TINT_HUE: f64 = 30.0;
+
+/// Smallest chroma of a color of the wallpaper that tints ANSI colors.
+const TINT_CHROMA: u8 = 40;
+
+/// Contrast ratio of ANSI colors and the accent to the background.
+const MIN_CONTRAST: f64 = 3.0;
+
+/// Contrast ratio of the foreground to the background.
+const TEXT_CONTRAST: f64 = 7.0;
+
+/// Whether a scheme has a dark or a light background.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    Dark,
+    Light,
+}
+
+impl FromStr for Mode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "dark" => Ok(Mode::Dark),
+            "light" => Ok(Mode::Light),
+            _ => Err(format!("unknown mode: {s}")),
+        }
+    }
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Mode::Dark => write!(f, "dark"),
+            Mode::Light => write!(f, "light"),
+        }
+    }
+}
+
+impl From<Tone> for Mode {
+    /// Light wallpapers get light schemes, everything else dark ones.
+    fn from(tone: Tone) -> Self {
+        match tone {
+            Tone::Light => Mode::Light,
+            Tone::Dark | Tone::Mixed => Mode::Dark,
+        }
+    }
+}
+
+/// A terminal color scheme.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Scheme {
+    pub mode: Mode,
+    pub background: Color,
+    pub foreground: Color,
+    pub cursor: Color,
+    pub selection: Color,
+    /// The most striking color of the wallpaper, readable on the background.
+    pub accent: Color,
+    /// Black or white, whichever is more readable on the accent.
+    pub accent_foreground: Color,
+    /// The 16 ANSI colors: black, red, green, yellow, blue, magenta, cyan and white, then their bright variants.
+    pub colors: [Color; 16],
+}
+
+impl Scheme {
+    /// Derives a scheme from the colors of a wallpaper.
+    ///
+    /// The background and grays take the hue of the most common color, and the ANSI colors are pulled toward
+    /// similar colors of the wallpaper, while all of them stay readable on the background.
+    pub fn of(palette: &Palette, mode: Mode) -> Self {
+        let base = palette.dominant.first().map_or(palette.average, |swatch| swatch.color);
+        let (hue, saturation, _) = hsl(base);
+        let tint = saturation.min(0.25);
+
+        let background = match mode {
+            Mode::Dark => from_hsl(hue, tint, 0.09),
+            Mode::Light => from_hsl(hue, tint, 0.94),
+        };
+        let foreground = readable(
+            match mode {
+                Mode::Dark => from_hsl(hue, tint.min(0.15), 0.86),
+                Mode::Light => from_hsl(hue, tint.min(0.15), 0.18),
+            },
+            background,
+            TEXT_CONTRAST,
+        );
+
+        let colorful = palette
+            .dominant
+            .iter()
+            .map(|swatch| swatch.color)
+            .chain([palette.accent])
+            .filter(|color| color.chroma() >= TINT_CHROMA)
+            .collect::<Vec<_>>();
+        let saturation = if colorful.is_empty() {
+            0.6
+        } else {
+            (colorful.iter().map(|color| hsl(*color).1).sum::<f64>() / colorful.len() as f64).clamp(0.45, 0.75)
+        };
+        let (normal, bright) = match mode {
+            Mode::Dark => (0.6, 0.72),
+            Mode::Light => (0.42, 0.5),
+        };
+
+        let mut colors = [Color::BLACK; 16];
+        for (i, lightness) in [0.18, 0.78, 0.42, 0.95].into_iter().enumerate() {
+            colors[[0, 7, 8, 15][i]] = from_hsl(hue, tint.min(0.12), lightness);
+        }
+        for (i, base) in HUES.into_iter().enumerate() {
+            // Halfway to the nearest similar color of the wallpaper, so that red stays red.
+            let hue = colorful
+                .iter()
+                .map(|color| hsl(*color).0)
+                .filter(|hue| hue_distance(*hue, base) <= TINT_HUE)
+                .min_by(|a, b| hue_distance(*a, base).total_cmp(&hue_distance(*b, base)))
+                .map_or(base, |hue| blend_hue(base, hue, 0.5));
+
+            colors[i + 1] = readable(from_hsl(hue, saturation, normal), background, MIN_CONTRAST);
+            colors[i + 9] = readable(
+                from_hsl(hue, (saturation + 0.1).min(0.9), bright),
+                background,
+                MIN_CONTRAST,
+            );
+        }
+
+        let accent = if palette.accent.chroma() >= TINT_CHROMA {
+            readable(palette.accent, background, MIN_CONTRAST)
+        } else {
+            colors[4]
+        };
+
+        Self {
+            mode,
+            background,
+            foreground,
+            cursor: foreground,
+            selection: mix(background, accent, 0.3),
+            accent,
+            accent_foreground: if Color::WHITE.contrast(accent) >= Color::BLACK.contrast(accent) {
+                Color::WHITE
+            } else {
+                Color::BLACK
+            },
+            colors,
+        }
+    }
+
+    /// Returns the scheme in a format.
+    pub fn render(&self, format: Format) -> String {
+        match format {
+            Format::Json => serde_json::to_string_pretty(self).unwrap_or_default() + "\n",
+            Format::Xresources => self.xresources(),
+            Format::Kitty => self.kitty(),
+            Format::Alacritty => self.alacritty(),
+            Format::Foot => self.foot(),
+            Format::Css => self.css(),
+        }
+    }
+
+    fn xresources(&self) -> String {
+        let mut out = format!(
+            "*.background: {}\n*.foreground: {}\n*.cursorColor: {}\n",
+            self.background, self.foreground, self.cursor
+        );
+        for (i, color) in self.colors.iter().enumerate() {
+            out += &format!("*.color{i}: {color}\n");
+        }
+        out
+    }
+
+    fn kitty(&self) -> String {
+        let mut out = format!(
+            "background {}\nforeground {}\ncursor {}\nselection_background {}\nselection_foreground {}\n\
+             active_border_color {}\n",
+            self.background, self.foreground, self.cursor, self.selection, self.foreground, self.accent
+        );
+        for (i, color) in self.colors.iter().enumerate() {
+            out += &format!("color{i} {color}\n");
+        }
+        out
+    }
+
+    fn alacritty(&self) -> String {
+        const NAMES: [&str; 8] = ["black", "red", "green", "yellow", "blue", "magenta", "cyan", "white"];
+
+        let mut out = format!(
+            "[colors.primary]\nbackground = \"{}\"\nforeground = \"{}\"\n\n\
+             [colors.cursor]\ncursor = \"{}\"\ntext = \"{}\"\n\n\
+             [colors.selection]\nbackground = \"{}\"\ntext = \"{}\"\n",
+            self.background, self.foreground, self.cursor, self.background, self.selection, self.foreground
+        );
+        for (section, colors) in [("normal", &self.colors[..8]), ("bright", &self.colors[8..])] {
+            out += &format!("\n[colors.{section}]\n");
+            for (name, color) in NAMES.iter().zip(colors) {
+                out += &format!("{name} = \"{color}\"\n");
+            }
+        }
+        out
+    }
+
+    fn foot(&self) -> String {
+        // foot takes colors without the leading `#`.
+        let hex = |color: Color| color.to_string().trim_start_matches('#').to_string();
+
+        let mut out = format!(
+            "[colors]\nbackground={}\nforeground={}\nselection-background={}\nselection-foreground={}\n",
+            hex(self.background),
+            hex(self.foreground),
+            hex(self.selection),
+            hex(self.foreground)
+        );
+        for (i, color) in self.colors[..8].iter().enumerate() {
+            out += &format!("regular{i}={}\n", hex(*color));
+        }
+        for (i, color) in self.colors[8..].iter().enumerate() {
+            out += &format!("bright{i}={}\n", hex(*color));
+        }
+        out
+    }
+
+    fn css(&self) -> String {
+        let mut out = format!(
+            ":root {{\n  --background: {};\n  --foreground: {};\n  --cursor: {};\n  --selection: {};\n  \
+             --accent: {};\n  --accent-foreground: {};\n",
+            self.background, self.foreground, self.cursor, self.selection, self.accent, self.accent_foreground
+        );
+        for (i, color) in self.colors.iter().enumerate() {
+            out += &format!("  --color{i}: {color};\n");
+        }
+        out + "}\n"
+    }
+}
+
+/// Format a scheme is exported in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    Json,
+    Xresources,
+    Kitty,
+    /// TOML config of alacritty.
+    Alacritty,
+    Foot,
+    /// Custom properties on `:root`.
+    Css,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "json" => Ok(Format::Json),
+            "xresources" => Ok(Format::Xresources),
+            "kitty" => Ok(Format::Kitty),
+            "alacritty" => Ok(Format::Alacritty),
+            "foot" => Ok(Format::Foot),
+            "css" => Ok(Format::Css),
+            _ => Err(format!("unknown scheme format: {s}")),
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Format::Json => write!(f, "json"),
+            Format::Xresources => write!(f, "xresources"),
+            Format::Kitty => write!(f, "kitty"),
+            Format::Alacritty => write!(f, "alacritty"),
+            Format::Foot => write!(f, "foot"),
+            Format::Css => write!(f, "css"),
+        }
+    }
+}
+
+/// A file the scheme of the newest Bing wallpaper is written to after saving, so that the desktop follows it.
+///
+/// ```toml
+/// [[schemes]]
+/// format = "kitty"
+/// path = "/home/me/.config/kitty/wallpaper.conf"
+///
+/// [[schemes]]
+/// format = "css"
+/// path = "/home/me/.config/waybar/wallpaper.css"
+/// # Follows the tone of the wallpaper if unset.
+/// mode = "dark"
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Export {
+    pub format: Format,
+    pub path: PathBuf,
+    pub mode: Option<Mode>,
+}
+
+impl Export {
+    /// Writes the scheme of a palette, replacing the file at once so that programs watching it never read half of
+    /// it.
+    pub fn write(&self, palette: &Palette, tone: Tone) -> Result<(), Box<dyn Error>> {
+        let scheme = Scheme::of(palette, self.mode.unwrap_or(tone.into()));
+
+        if let Some(dir) = self.path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        util::write_atomic(&self.path, |tmp| Ok(fs::write(tmp, scheme.render(self.format))?))
+    }
+}
+
+/// Returns hue in degrees, saturation and lightness, both from 0 to 1.
+fn hsl(color: Color) -> (f64, f64, f64) {
+    let [r, g, b] = color.0.map(|c| c as f64 / 255.0);
+    let (max, min) = (r.max(g).max(b), r.min(g).min(b));
+    let lightness = (max + min) / 2.0;
+    if max == min {
+        return (0.0, 0.0, lightness);
+    }
+
+    let delta = max - min;
+    let saturation = delta / (1.0 - (2.0 * lightness - 1.0).abs());
+    let hue = if max == r {
+        60.0 * ((g - b) / delta).rem_euclid(6.0)
+    } else if max == g {
+        60.0 * ((b - r) / delta + 2.0)
+    } else {
+        60.0 * ((r - g) / delta + 4.0)
+    };
+
+    (hue, saturation.min(1.0), lightness)
+}
+
+fn from_hsl(hue: f64, saturation: f64, lightness: f64) -> Color {
+    let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
+    let h = hue.rem_euclid(360.0) / 60.0;
+    let x = chroma * (1.0 - (h % 2.0 - 1.0).abs());
+    let (r, g, b) = match h as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let m = lightness - chroma / 2.0;
+
+    Color([r, g, b].map(|c| ((c + m) * 255.0).round().clamp(0.0, 255.0) as u8))
+}
+
+fn hue_distance(a: f64, b: f64) -> f64 {
+    let d = (a - b).rem_euclid(360.0);
+    d.min(360.0 - d)
+}
+
+/// Returns the hue `t` of the way from `a` to `b`, around the shorter side of the circle.
+fn blend_hue(a: f64, b: f64, t: f64) -> f64 {
+    let d = (b - a + 540.0).rem_euclid(360.0) - 180.0;
+    (a + d * t).rem_euclid(360.0)
+}
+
+fn mix(a: Color, b: Color, t: f64) -> Color {
+    Color(std::array::from_fn(|i| {
+        (a.0[i] as f64 + (b.0[i] as f64 - a.0[i] as f64) * t).round() as u8
+    }))
+}
+
+/// Moves the lightness of a color away from the background until it has the contrast.
+fn readable(color: Color, background: Color, contrast: f64) -> Color {
+    let (hue, saturation, mut lightness) = hsl(color);
+    let step = if background.luminance() > 0.18 { -0.02 } else { 0.02 };
+
+    let mut color = color;
+    while color.contrast(background) < contrast && (0.0..=1.0).contains(&(lightness + step)) {
+        lightness += step;
+        color = from_hsl(hue, saturation, lightness);
+    }
+    color
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::palette::Swatch;
+
+    #[test]
+    fn test_scheme() {
+        // A sunset over the sea.
+        let palette = Palette {
+            dominant: vec![
+                Swatch {
+                    color: Color([20, 40, 80]),
+                    share: 0.5,
+                },
+                Swatch {
+                    color: Color([240, 120, 40]),
+                    share: 0.3,
+                },
+                Swatch {
+                    color: Color([200, 60, 60]),
+                    share: 0.2,
+                },
+            ],
+            average: Color([90, 70, 70]),
+            accent: Color([240, 120, 40]),
+            contrast: Color::WHITE,
+        };
+
+        for mode in [Mode::Dark, Mode::Light] {
+            let scheme = Scheme::of(&palette, mode);
+            assert_eq!(scheme.mode, mode);
+            assert!(scheme.foreground.contrast(scheme.background) >= TEXT_CONTRAST);
+            for color in scheme.colors[1..7]
+                .iter()
+                .chain(&scheme.colors[9..15])
+                .chain([&scheme.accent])
+            {
+                assert!(
+                    color.contrast(scheme.background) >= MIN_CONTRAST,
+                    "{color} on {}",
+                    scheme.background
+                );
+            }
+            // The background is blue like the sea, and red stays red.
+            let (hue, _, _) = hsl(scheme.background);
+            assert!(hue_distance(hue, 220.0) < 20.0);
+            assert!(hue_distance(hsl(scheme.colors[1]).0, 0.0) < TINT_HUE);
+        }
+
+        let scheme = Scheme::of(&palette, Mode::Dark);
+        assert!(
+            scheme
+                .render(Format::Kitty)
+                .contains(&format!("color9 {}\n", scheme.colors[9]))
+        );
+        assert!(scheme.render(Format::Foot).contains("regular0="));
+        assert!(scheme.render(Format::Alacritty).contains("[colors.bright]\nblack = \""));
+        let json: Scheme = serde_json::from_str(&scheme.render(Format::Json)).unwrap();
+        assert_eq!(json, scheme);
+    }
+}
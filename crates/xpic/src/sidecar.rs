@@ -0,0 +1,176 @@
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::metadata::{Metadata, iso_date};
+
+/// Format of a metadata file written next to an image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    /// The metadata as JSON.
+    Json,
+    /// Adobe XMP packet, read by photo managers.
+    Xmp,
+}
+
+pub const FORMATS: [Format; 2] = [Format::Json, Format::Xmp];
+
+impl Format {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Json => "json",
+            Format::Xmp => "xmp",
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "json" => Ok(Format::Json),
+            "xmp" => Ok(Format::Xmp),
+            _ => Err(format!("unknown sidecar format: {s}")),
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.extension())
+    }
+}
+
+/// Returns path of the sidecar of an image, e.g. `image.jpg.xmp` for `image.jpg`. The extension of the image is kept,
+/// so that a converted image and its kept original have sidecars of their own.
+pub fn path(image: impl AsRef<Path>, format: Format) -> PathBuf {
+    let image = image.as_ref();
+    let mut name = image.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(format.extension());
+
+    image.with_file_name(name)
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Returns an XMP packet with the title, copyright, date and links of the metadata.
+pub fn to_xmp(metadata: &Metadata) -> String {
+    let mut properties = String::new();
+
+    let mut alt = |name: &str, value: &Option<String>| {
+        if let Some(value) = value {
+            properties.push_str(&format!(
+                "   <{name}>\n    <rdf:Alt>\n     <rdf:li xml:lang=\"x-default\">{}</rdf:li>\n    </rdf:Alt>\n   </{name}>\n",
+                escape(value)
+            ));
+        }
+    };
+    alt("dc:title", &metadata.title);
+    alt(
+        "dc:description",
+        &metadata.description.clone().or(metadata.title.clone()),
+    );
+    alt("dc:rights", &metadata.copyright);
+
+    if let Some(holder) = metadata.copyright_holder() {
+        properties.push_str(&format!(
+            "   <dc:creator>\n    <rdf:Seq>\n     <rdf:li>{}</rdf:li>\n    </rdf:Seq>\n   </dc:creator>\n",
+            escape(holder)
+        ));
+    }
+
+    let mut simple = |name: &str, value: Option<String>| {
+        if let Some(value) = value {
+            properties.push_str(&format!("   <{name}>{}</{name}>\n", escape(&value)));
+        }
+    };
+    simple("photoshop:Credit", metadata.copyright.clone());
+    simple("photoshop:DateCreated", metadata.date.as_deref().map(iso_date));
+    simple("dc:source", metadata.url.clone());
+    simple("xmpRights:WebStatement", metadata.link.clone());
+    simple("dc:identifier", Some(metadata.id.clone()));
+
+    format!(
+        r#"<?xpacket begin="" id="W5M0MpCehiHzreSzNTczkc9d"?>
+<x:xmpmeta xmlns:x="adobe:ns:meta/">
+ <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+  <rdf:Description rdf:about=""
+    xmlns:dc="http://purl.org/dc/elements/1.1/"
+    xmlns:photoshop="http://ns.adobe.com/photoshop/1.0/"
+    xmlns:xmpRights="http://ns.adobe.com/xap/1.0/rights/">
+{properties}  </rdf:Description>
+ </rdf:RDF>
+</x:xmpmeta>
+<?xpacket end="w"?>"#
+    )
+}
+
+/// Writes sidecars of an image and returns their paths.
+///
+/// Nothing is written for images without title or copyright, such as local Windows Spotlight assets.
+pub fn write(image: impl AsRef<Path>, metadata: &Metadata, formats: &[Format]) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    if !metadata.has_credits() {
+        return Ok(Vec::new());
+    }
+
+    let mut paths = Vec::new();
+
+    for &format in formats {
+        let path = path(&image, format);
+        let content = match format {
+            Format::Json => serde_json::to_string_pretty(metadata)?,
+            Format::Xmp => to_xmp(metadata),
+        };
+
+        fs::write(&path, content).map_err(|e| format!("failed to write {}: {}", path.display(), e))?;
+        paths.push(path);
+    }
+
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::Source;
+
+    #[test]
+    fn test_write() {
+        let dir = tempfile::tempdir().unwrap();
+        let image = dir.path().join("OHR.Lighthouse_EN-US123_UHD.jpg");
+
+        let mut metadata = Metadata::new(Source::Bing, "OHR.Lighthouse_EN-US123_UHD.jpg");
+        assert!(write(&image, &metadata, &FORMATS).unwrap().is_empty());
+
+        metadata.title = Some("Lighthouse & sea".to_string());
+        metadata.copyright = Some("© Photographer".to_string());
+        metadata.date = Some("20240131".to_string());
+
+        let paths = write(&image, &metadata, &FORMATS).unwrap();
+        assert_eq!(
+            paths,
+            vec![
+                dir.path().join("OHR.Lighthouse_EN-US123_UHD.jpg.json"),
+                dir.path().join("OHR.Lighthouse_EN-US123_UHD.jpg.xmp")
+            ]
+        );
+
+        let json: Metadata = serde_json::from_str(&fs::read_to_string(&paths[0]).unwrap()).unwrap();
+        assert_eq!(json, metadata);
+
+        let xmp = fs::read_to_string(&paths[1]).unwrap();
+        assert!(xmp.contains("<rdf:li xml:lang=\"x-default\">Lighthouse &amp; sea</rdf:li>"));
+        assert!(xmp.contains("<photoshop:DateCreated>2024-01-31</photoshop:DateCreated>"));
+    }
+}
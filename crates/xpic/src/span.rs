@@ -0,0 +1,370 @@
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::resize::Resize;
+use crate::util;
+
+/// Pixels per millimeter of monitors of unknown physical size, i.e. 96 DPI.
+const DEFAULT_DENSITY: f64 = 96.0 / 25.4;
+
+/// A monitor of a desktop.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Monitor {
+    /// Output name, such as `DP-1`.
+    pub name: String,
+    /// Resolution in pixels.
+    pub width: u32,
+    pub height: u32,
+    /// Position of the top left corner on the desktop, in pixels.
+    #[serde(default)]
+    pub x: i32,
+    #[serde(default)]
+    pub y: i32,
+    /// Physical size of the visible area in millimeters.
+    pub width_mm: Option<f64>,
+    pub height_mm: Option<f64>,
+}
+
+impl Monitor {
+    /// Returns pixels per millimeter, if the physical size is known.
+    fn density(&self) -> Option<f64> {
+        self.width_mm
+            .filter(|width_mm| *width_mm > 0.0)
+            .map(|width_mm| self.width as f64 / width_mm)
+    }
+}
+
+impl FromStr for Monitor {
+    type Err = String;
+
+    /// Parses `NAME:WxH+X+Y`, optionally followed by the physical size as `@WMMxHMM`, e.g.
+    /// `DP-1:2560x1440+0+0@597x336`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid monitor: {s}, expected NAME:WxH+X+Y or NAME:WxH+X+Y@WMMxHMM");
+
+        let (name, geometry) = s.split_once(':').ok_or_else(invalid)?;
+        let (geometry, physical) = match geometry.split_once('@') {
+            Some((geometry, physical)) => (geometry, Some(physical)),
+            None => (geometry, None),
+        };
+
+        // Coordinates start with their sign, a negative one also as `+-`, as xrandr prints it.
+        let sign = geometry.find(['+', '-']).ok_or_else(invalid)?;
+        let (size, position) = geometry.split_at(sign);
+        let (width, height) = size.split_once(['x', 'X']).ok_or_else(invalid)?;
+        let second = position[1..].find(['+', '-']).ok_or_else(invalid)? + 1;
+        let (x, y) = position.split_at(second);
+        let coordinate = |c: &str| c.strip_prefix('+').unwrap_or(c).parse::<i32>().map_err(|_| invalid());
+
+        let (width_mm, height_mm) = match physical {
+            Some(physical) => {
+                let (width_mm, height_mm) = physical.split_once(['x', 'X']).ok_or_else(invalid)?;
+                (
+                    Some(width_mm.parse().map_err(|_| invalid())?),
+                    Some(height_mm.parse().map_err(|_| invalid())?),
+                )
+            }
+            None => (None, None),
+        };
+
+        Ok(Monitor {
+            name: name.to_string(),
+            width: width.parse().map_err(|_| invalid())?,
+            height: height.parse().map_err(|_| invalid())?,
+            x: coordinate(x)?,
+            y: coordinate(y)?,
+            width_mm,
+            height_mm,
+        })
+    }
+}
+
+/// Monitors a wallpaper is spread over.
+///
+/// ```toml
+/// [span]
+/// # Gap between the pictures of adjacent monitors, in millimeters.
+/// bezel = 15.0
+///
+/// [[span.monitors]]
+/// name = "DP-1"
+/// width = 3840
+/// height = 2160
+/// width_mm = 597
+/// height_mm = 336
+///
+/// [[span.monitors]]
+/// name = "HDMI-A-1"
+/// width = 1920
+/// height = 1080
+/// x = 3840
+/// width_mm = 527
+/// height_mm = 296
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Span {
+    pub monitors: Vec<Monitor>,
+    /// Width of the bezels between the visible areas of adjacent monitors, in millimeters. The part of the picture
+    /// behind them is skipped, so that lines continue straight across monitors.
+    #[serde(default)]
+    pub bezel: f64,
+}
+
+/// A rectangle in millimeters.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Rect {
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+}
+
+fn overlaps(a: (i64, i64), b: (i64, i64)) -> bool {
+    a.0 < b.1 && b.0 < a.1
+}
+
+impl Span {
+    /// Returns where the visible area of each monitor physically is, in the order of the monitors.
+    ///
+    /// The desktop only tells where monitors are in pixels, and a pixel has a different size on each monitor when
+    /// their densities differ. Monitors that touch on the desktop are therefore placed against each other, with the
+    /// bezel in between, and offsets along the shared edge are converted with the density of the neighbor.
+    fn placements(&self) -> Vec<Rect> {
+        let known = self.monitors.iter().filter_map(Monitor::density).collect::<Vec<_>>();
+        let fallback = if known.is_empty() {
+            DEFAULT_DENSITY
+        } else {
+            known.iter().sum::<f64>() / known.len() as f64
+        };
+        let density = |monitor: &Monitor| monitor.density().unwrap_or(fallback);
+
+        let mut order = (0..self.monitors.len()).collect::<Vec<_>>();
+        order.sort_by_key(|&i| (self.monitors[i].x, self.monitors[i].y));
+
+        let mut rects: Vec<Option<Rect>> = vec![None; self.monitors.len()];
+        for i in order {
+            let monitor = &self.monitors[i];
+            let d = density(monitor);
+            let width = monitor.width as f64 / d;
+            let height = monitor.height_mm.unwrap_or(monitor.height as f64 / d);
+
+            let rows = |m: &Monitor| (m.y as i64, m.y as i64 + m.height as i64);
+            let columns = |m: &Monitor| (m.x as i64, m.x as i64 + m.width as i64);
+
+            let placed = |j: usize| rects[j].map(|rect| (&self.monitors[j], rect));
+            let left = (0..self.monitors.len()).filter_map(placed).find(|(other, _)| {
+                other.x as i64 + other.width as i64 == monitor.x as i64 && overlaps(rows(other), rows(monitor))
+            });
+            let above = (0..self.monitors.len()).filter_map(placed).find(|(other, _)| {
+                other.y as i64 + other.height as i64 == monitor.y as i64 && overlaps(columns(other), columns(monitor))
+            });
+
+            let x = match (left, above) {
+                (Some((_, rect)), _) => rect.x + rect.width + self.bezel,
+                (None, Some((other, rect))) => rect.x + (monitor.x - other.x) as f64 / density(other),
+                (None, None) => monitor.x as f64 / d,
+            };
+            let y = match (above, left) {
+                (Some((_, rect)), _) => rect.y + rect.height + self.bezel,
+                (None, Some((other, rect))) => rect.y + (monitor.y - other.y) as f64 / density(other),
+                (None, None) => monitor.y as f64 / d,
+            };
+
+            rects[i] = Some(Rect { x, y, width, height });
+        }
+
+        let rects = rects.into_iter().flatten().collect::<Vec<_>>();
+        let (left, top) = rects.iter().fold((f64::INFINITY, f64::INFINITY), |(x, y), rect| {
+            (x.min(rect.x), y.min(rect.y))
+        });
+
+        rects
+            .into_iter()
+            .map(|rect| Rect {
+                x: rect.x - left,
+                y: rect.y - top,
+                ..rect
+            })
+            .collect()
+    }
+
+    /// Returns the part of the picture each monitor shows, at its resolution, in the order of the monitors.
+    ///
+    /// The picture is scaled to cover the physical extent of all monitors, at the density of the sharpest one, and
+    /// centered.
+    pub fn split(&self, img: &DynamicImage) -> Result<Vec<DynamicImage>, Box<dyn Error>> {
+        if self.monitors.is_empty() {
+            return Err("no monitors".into());
+        }
+        if self
+            .monitors
+            .iter()
+            .any(|monitor| monitor.width == 0 || monitor.height == 0)
+        {
+            return Err("monitors must have a resolution".into());
+        }
+
+        let rects = self.placements();
+        let density = self
+            .monitors
+            .iter()
+            .filter_map(Monitor::density)
+            .fold(None, |max: Option<f64>, d| Some(max.map_or(d, |max| max.max(d))))
+            .unwrap_or(DEFAULT_DENSITY);
+
+        let (width, height) = rects.iter().fold((0.0, 0.0), |(w, h): (f64, f64), rect| {
+            (w.max(rect.x + rect.width), h.max(rect.y + rect.height))
+        });
+        let canvas = Resize::new(
+            (width * density).round().max(1.0) as u32,
+            (height * density).round().max(1.0) as u32,
+        )
+        .apply(img, None);
+
+        Ok(self
+            .monitors
+            .iter()
+            .zip(rects)
+            .map(|(monitor, rect)| {
+                let x = ((rect.x * density).round() as u32).min(canvas.width() - 1);
+                let y = ((rect.y * density).round() as u32).min(canvas.height() - 1);
+                let width = ((rect.width * density).round() as u32).clamp(1, canvas.width() - x);
+                let height = ((rect.height * density).round() as u32).clamp(1, canvas.height() - y);
+
+                canvas
+                    .crop_imm(x, y, width, height)
+                    .resize_exact(monitor.width, monitor.height, FilterType::Lanczos3)
+            })
+            .collect())
+    }
+
+    /// Returns one image covering the whole desktop, with the part of the picture each monitor shows at its position.
+    pub fn span(&self, img: &DynamicImage) -> Result<DynamicImage, Box<dyn Error>> {
+        let slices = self.split(img)?;
+
+        let left = self.monitors.iter().map(|monitor| monitor.x as i64).min().unwrap_or(0);
+        let top = self.monitors.iter().map(|monitor| monitor.y as i64).min().unwrap_or(0);
+        let right = self
+            .monitors
+            .iter()
+            .map(|m| m.x as i64 + m.width as i64)
+            .max()
+            .unwrap_or(0);
+        let bottom = self
+            .monitors
+            .iter()
+            .map(|m| m.y as i64 + m.height as i64)
+            .max()
+            .unwrap_or(0);
+
+        let (width, height) = ((right - left) as u32, (bottom - top) as u32);
+        let mut desktop = RgbaImage::from_pixel(width, height, image::Rgba([0, 0, 0, 255]));
+        for (monitor, slice) in self.monitors.iter().zip(slices) {
+            imageops::replace(
+                &mut desktop,
+                &slice.to_rgba8(),
+                monitor.x as i64 - left,
+                monitor.y as i64 - top,
+            );
+        }
+
+        Ok(DynamicImage::ImageRgba8(desktop))
+    }
+
+    /// Writes the part of the image at `path` each monitor shows to `dir`, named after the image and the monitor, and
+    /// returns the monitor names with the paths.
+    pub fn split_to(
+        &self,
+        path: impl AsRef<Path>,
+        dir: impl AsRef<Path>,
+    ) -> Result<Vec<(String, PathBuf)>, Box<dyn Error>> {
+        let path = path.as_ref();
+        let slices = self.split(&util::open_image(path)?)?;
+
+        let mut written = Vec::new();
+        for (monitor, slice) in self.monitors.iter().zip(slices) {
+            let dst = output_path(path, dir.as_ref(), &monitor.name);
+            util::save_derived_image(&slice, path, &dst)?;
+            written.push((monitor.name.clone(), dst));
+        }
+
+        Ok(written)
+    }
+
+    /// Writes one image of the image at `path` covering the whole desktop to `dir`, and returns its path.
+    pub fn span_to(&self, path: impl AsRef<Path>, dir: impl AsRef<Path>) -> Result<PathBuf, Box<dyn Error>> {
+        let path = path.as_ref();
+        let dst = output_path(path, dir.as_ref(), "span");
+        util::save_derived_image(&self.span(&util::open_image(path)?)?, path, &dst)?;
+
+        Ok(dst)
+    }
+}
+
+fn output_path(path: &Path, dir: &Path, suffix: &str) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let suffix = suffix.replace(['/', '\\'], "_");
+
+    match path.extension() {
+        Some(ext) => dir.join(format!("{stem}_{suffix}.{}", ext.to_string_lossy())),
+        None => dir.join(format!("{stem}_{suffix}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    #[test]
+    fn test_span() {
+        // A 4K and a 1080p monitor of about the same physical size, side by side, with 20 mm of bezels between them.
+        let span = Span {
+            monitors: vec![
+                "DP-1:400x200+0+0@400x200".parse().unwrap(),
+                "HDMI-A-1:200x100+400+0@400x200".parse().unwrap(),
+            ],
+            bezel: 20.0,
+        };
+        assert_eq!(
+            span.placements(),
+            vec![
+                Rect {
+                    x: 0.0,
+                    y: 0.0,
+                    width: 400.0,
+                    height: 200.0
+                },
+                Rect {
+                    x: 420.0,
+                    y: 0.0,
+                    width: 400.0,
+                    height: 200.0
+                },
+            ]
+        );
+
+        // A horizontal gradient lines up: each monitor shows its half, without the part behind the bezels.
+        let img = DynamicImage::ImageRgb8(RgbImage::from_fn(820, 200, |x, _| Rgb([(x * 255 / 819) as u8, 0, 0])));
+        let slices = span.split(&img).unwrap();
+        assert_eq!(slices[0].to_rgb8().dimensions(), (400, 200));
+        assert_eq!(slices[1].to_rgb8().dimensions(), (200, 100));
+        let red = |slice: &DynamicImage, x: u32| slice.to_rgb8().get_pixel(x, 50)[0] as i32;
+        assert!((red(&slices[0], 399) - 124).abs() <= 3);
+        assert!((red(&slices[1], 0) - 131).abs() <= 3);
+
+        let desktop = span.span(&img).unwrap();
+        assert_eq!((desktop.width(), desktop.height()), (600, 200));
+    }
+
+    #[test]
+    fn test_parse_monitor() {
+        let monitor: Monitor = "eDP-1:1920x1200-1920+-100".parse().unwrap();
+        assert_eq!((monitor.x, monitor.y, monitor.width_mm), (-1920, -100, None));
+        assert!("DP-1:1920x1080".parse::<Monitor>().is_err());
+    }
+}
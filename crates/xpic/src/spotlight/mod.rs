@@ -0,0 +1,304 @@
+pub mod online;
+pub mod watch;
+
+use std::collections::HashSet;
+use std::error::Error;
+use std::{env, fs};
+
+use std::path::{Path, PathBuf};
+
+use crate::config::SaveOptions;
+use crate::dedup::{self, Candidate, Dedup, Dropped};
+use crate::filter::Filter;
+use crate::library::Library;
+use crate::metadata::{Metadata, Source};
+use crate::util;
+use crate::util::{Report, Saved};
+
+/// Returns asset directories.
+pub fn get_asset_dirs() -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let local_app_data = env::var("LocalAppData")
+        .map(PathBuf::from)
+        .map_err(|e| format!("failed to get LocalAppData: {e}"))?;
+
+    let pattern = local_app_data.join(r"Packages\*ContentDeliveryManager*\LocalState\Assets");
+
+    let dirs = glob::glob(pattern.to_str().unwrap())?
+        .filter_map(Result::ok)
+        .filter(|path| path.is_dir())
+        .collect::<Vec<_>>();
+
+    Ok(dirs)
+}
+
+/// Returns assets.
+pub fn get_assets() -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let files = get_asset_dirs()?
+        .into_iter()
+        .filter_map(|dir| fs::read_dir(dir).ok())
+        .flat_map(|entries| entries.filter_map(Result::ok).map(|entry| entry.path()))
+        .filter(|path| path.is_file())
+        .collect::<Vec<_>>();
+
+    Ok(files)
+}
+
+/// Returns the default filter for assets(width >= 1920 and height >= 1080).
+pub fn default_filter() -> Filter {
+    Filter::MinSize {
+        width: 1920,
+        height: 1080,
+    }
+}
+
+/// Returns the filter for assets: the default filter, narrowed by `filter` if given. Assets also include icons and
+/// tiles, which a user filter alone may let through.
+pub fn asset_filter(filter: Option<&Filter>) -> Filter {
+    match filter {
+        Some(filter) => Filter::All(vec![default_filter(), filter.clone()]),
+        None => default_filter(),
+    }
+}
+
+/// Returns metadata of an asset. Fails if the asset is not an image.
+pub fn get_metadata(path: impl AsRef<Path>) -> Result<Metadata, Box<dyn Error>> {
+    let path = path.as_ref();
+    let (width, height) = util::get_image_dimensions(path)?;
+
+    let mut metadata = Metadata::new(
+        Source::Spotlight,
+        path.file_name().ok_or("failed to get filename")?.to_string_lossy(),
+    );
+    metadata.width = Some(width);
+    metadata.height = Some(height);
+    metadata.file_size = Some(fs::metadata(path)?.len());
+
+    Ok(metadata)
+}
+
+/// Returns whether the asset is an image matching the filter.
+pub fn is_image(path: impl AsRef<Path>, filter: &Filter) -> Result<bool, Box<dyn Error>> {
+    Ok(filter.matches(&get_metadata(path)?))
+}
+
+/// Returns whether the asset was pruned from the library.
+fn is_pruned(library: &Library, path: &Path) -> bool {
+    path.file_name().is_some_and(|name| {
+        library
+            .catalog()
+            .is_pruned(Source::Spotlight, &name.to_string_lossy())
+            .unwrap_or(false)
+    })
+}
+
+/// Returns images matching the filter.
+pub fn get_images(filter: &Filter) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let assets = get_assets().map_err(|e| format!("failed to get assets: {e}"))?;
+
+    let images = assets
+        .into_iter()
+        .filter_map(|path| match is_image(&path, filter) {
+            Ok(true) => Some(path),
+            Ok(false) => None,
+            Err(e) => {
+                eprintln!("failed to open image: {e}");
+                None
+            }
+        })
+        .collect::<Vec<_>>();
+
+    Ok(images)
+}
+
+/// Groups visually identical images and keeps the best copy of each.
+pub fn dedup_images(images: Vec<PathBuf>, dedup: Dedup) -> (Vec<PathBuf>, Vec<Dropped>) {
+    let mut unhashed = Vec::new();
+
+    let candidates = images
+        .into_iter()
+        .filter_map(|path| match Candidate::new(&path) {
+            Ok(candidate) => Some(candidate),
+            Err(e) => {
+                eprintln!("failed to hash image {}: {}", path.display(), e);
+                unhashed.push(path);
+                None
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let (kept, dropped) = dedup::dedup(candidates, dedup);
+
+    let mut images = kept.into_iter().map(|candidate| candidate.path).collect::<Vec<_>>();
+    images.extend(unhashed);
+
+    (images, dropped)
+}
+
+/// Like `dedup_images`, but also compares assets not yet in the library with the pictures already cataloged, so that
+/// an asset is dropped if the library has a better copy of it from any source.
+pub fn dedup_new_images(
+    library: &Library,
+    images: Vec<PathBuf>,
+    dedup: Dedup,
+) -> Result<(Vec<PathBuf>, Vec<Dropped>), Box<dyn Error>> {
+    let mut kept = Vec::new();
+    let mut new = HashSet::new();
+    let mut candidates = library
+        .list()?
+        .iter()
+        .filter(|entry| entry.path.exists())
+        .filter_map(Candidate::from_entry)
+        .collect::<Vec<_>>();
+
+    for path in images {
+        // Saved assets are compared with the library after saving, by `dedup::dedup_library`.
+        let saved = match get_metadata(&path) {
+            Ok(metadata) => library.find_by_source(Source::Spotlight, &metadata.id)?.is_some(),
+            Err(_) => false,
+        };
+        if saved {
+            kept.push(path);
+            continue;
+        }
+
+        match Candidate::new(&path) {
+            Ok(candidate) => {
+                new.insert(path);
+                candidates.push(candidate);
+            }
+            Err(e) => {
+                eprintln!("failed to hash image {}: {}", path.display(), e);
+                kept.push(path);
+            }
+        }
+    }
+
+    let (best, dropped) = dedup::dedup(candidates, dedup);
+    kept.extend(
+        best.into_iter()
+            .map(|candidate| candidate.path)
+            .filter(|path| new.contains(path)),
+    );
+    let dropped = dropped
+        .into_iter()
+        .filter(|dropped| new.contains(&dropped.path))
+        .collect();
+
+    Ok((kept, dropped))
+}
+
+/// Copies an asset into the library, naming it by content hash, and records it in the catalog.
+pub fn save_image(library: &Library, path: impl AsRef<Path>, save: &SaveOptions) -> Result<Saved, Box<dyn Error>> {
+    let path = path.as_ref();
+    let metadata = get_metadata(path)?;
+
+    // Saved images may have been renamed or converted since.
+    let existing = library.find_by_source(Source::Spotlight, &metadata.id)?;
+
+    let saved = match (existing, &save.template) {
+        (Some(entry), _) => Saved::Existing(entry.path),
+        (None, Some(template)) => library.place(path, &metadata, Some(template), save.layout, true)?,
+        (None, None) => library.copy_by_hash(path, &metadata, save.layout)?,
+    };
+    let entry = library.store(metadata, &saved, save)?;
+
+    Ok(match saved {
+        Saved::New(_) => Saved::New(entry.path),
+        Saved::Existing(_) => Saved::Existing(entry.path),
+    })
+}
+
+/// Copies images matching `filter` to a specified directory, naming them by content hash. Images already present are
+/// skipped.
+pub fn copy_images_to(
+    dst: impl AsRef<Path>,
+    filter: Option<&Filter>,
+    dedup: Option<Dedup>,
+    save: &SaveOptions,
+) -> Result<Report, Box<dyn Error>> {
+    let dst = dst.as_ref();
+    let library = Library::open(dst)?;
+
+    let filter = asset_filter(filter);
+    let mut images = get_images(&filter).map_err(|e| format!("failed to get images: {e}"))?;
+    images.retain(|path| !is_pruned(&library, path));
+
+    let mut report = Report::default();
+
+    let images = match dedup {
+        Some(dedup) => {
+            let (images, dropped) = dedup_new_images(&library, images, dedup)?;
+            report.dropped = dropped;
+            images
+        }
+        None => images,
+    };
+
+    images
+        .into_iter()
+        .for_each(|path| match save_image(&library, &path, save) {
+            Ok(saved) => report.add(&saved),
+            Err(err) => {
+                report.failed += 1;
+                eprintln!(
+                    "failed to copy image from {} to {}: {}",
+                    path.display(),
+                    dst.display(),
+                    err
+                )
+            }
+        });
+
+    // Link copies of the same picture saved from other sources or earlier runs.
+    if let Some(dedup) = dedup {
+        dedup::dedup_library(&library, dedup, false)?;
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::imageops::FilterType;
+    use image::{DynamicImage, Rgb, RgbImage};
+
+    #[test]
+    fn test_dedup_new_images() {
+        let library_dir = tempfile::tempdir().unwrap();
+        let assets = tempfile::tempdir().unwrap();
+        let library = Library::open(library_dir.path()).unwrap();
+
+        let picture = DynamicImage::ImageRgb8(RgbImage::from_fn(256, 128, |x, y| {
+            Rgb([(x % 256) as u8, (y * 2) as u8, ((x + y) % 256) as u8])
+        }));
+        let saved = library_dir.path().join("saved.png");
+        picture.save(&saved).unwrap();
+        library.add(Metadata::new(Source::Bing, "saved"), &saved).unwrap();
+
+        // A smaller copy of the saved picture, and a different picture.
+        let copy = assets.path().join("copy");
+        picture
+            .resize_exact(128, 64, FilterType::Triangle)
+            .save_with_format(&copy, image::ImageFormat::Png)
+            .unwrap();
+        let other = assets.path().join("other");
+        RgbImage::from_fn(
+            256,
+            128,
+            |x, _| if x < 128 { Rgb([255, 255, 255]) } else { Rgb([0, 0, 0]) },
+        )
+        .save_with_format(&other, image::ImageFormat::Png)
+        .unwrap();
+
+        let (kept, dropped) = dedup_new_images(&library, vec![copy.clone(), other.clone()], Dedup::default()).unwrap();
+        assert_eq!(kept, vec![other]);
+        assert_eq!(
+            dropped,
+            vec![Dropped {
+                path: copy,
+                kept: library.entry(&saved).unwrap().path
+            }]
+        );
+    }
+}
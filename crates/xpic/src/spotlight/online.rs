@@ -0,0 +1,334 @@
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use url::Url;
+
+use crate::config::SaveOptions;
+use crate::library::Library;
+pub use crate::metadata::Orientation;
+use crate::metadata::{Metadata, Source};
+use crate::util;
+use crate::util::{Report, Saved};
+
+/// Windows Spotlight content API.
+pub const DEFAULT_ENDPOINT: &str = "https://fd.api.iris.microsoft.com/v4/api/selection";
+
+/// Placement id used by the Windows Spotlight desktop experience.
+const PLACEMENT: &str = "88000820";
+
+/// The API returns at most 4 items per request.
+const MAX_BATCH_COUNT: usize = 4;
+
+#[derive(Serialize)]
+pub struct Query {
+    pub placement: &'static str,
+
+    #[serde(rename = "bcnt")]
+    pub batch_count: usize,
+
+    pub country: String,
+
+    pub locale: String,
+
+    #[serde(rename = "fmt")]
+    pub format: &'static str,
+}
+
+impl Query {
+    /// Returns query for the specified locale, e.g. `en-US`.
+    pub fn new(locale: &str) -> Self {
+        let country = locale
+            .rsplit_once('-')
+            .map(|(_, country)| country.to_ascii_uppercase())
+            .unwrap_or_else(|| "US".to_string());
+
+        Self {
+            locale: locale.to_string(),
+            country,
+            ..Self::default()
+        }
+    }
+}
+
+impl Default for Query {
+    fn default() -> Self {
+        Self {
+            placement: PLACEMENT,
+            batch_count: MAX_BATCH_COUNT,
+            country: "US".to_string(),
+            locale: "en-US".to_string(),
+            format: "json",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Image {
+    pub url: Url,
+    pub title: String,
+    pub copyright: String,
+    pub description: String,
+    /// Page with more about the picture.
+    pub link: Option<Url>,
+}
+
+impl Image {
+    /// Returns the last path segment of the image url.
+    pub fn id(&self) -> Option<String> {
+        self.url
+            .path_segments()?
+            .next_back()
+            .filter(|segment| !segment.is_empty())
+            .map(String::from)
+    }
+
+    pub fn metadata(&self, locale: &str) -> Metadata {
+        let mut metadata = Metadata::new(Source::Spotlight, self.id().unwrap_or_default());
+        metadata.title = Some(self.title.clone()).filter(|title| !title.is_empty());
+        metadata.copyright = Some(self.copyright.clone()).filter(|copyright| !copyright.is_empty());
+        metadata.description = Some(self.description.clone()).filter(|description| !description.is_empty());
+        metadata.market = Some(locale.to_string());
+        metadata.link = self.link.as_ref().map(Url::to_string);
+        metadata.url = Some(self.url.to_string());
+        metadata
+    }
+}
+
+#[derive(Deserialize)]
+struct Asset {
+    asset: Url,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Ad {
+    landscape_image: Asset,
+    portrait_image: Asset,
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    copyright: String,
+    #[serde(default)]
+    description: String,
+    /// Link opened by the "Learn more" button, e.g. `microsoft-edge:https://www.bing.com/search?q=...`.
+    #[serde(default)]
+    cta_uri: String,
+}
+
+#[derive(Deserialize)]
+struct ItemContent {
+    ad: Ad,
+}
+
+#[derive(Deserialize)]
+struct Item {
+    // The item is a JSON document encoded as a string.
+    item: String,
+}
+
+#[derive(Deserialize)]
+struct BatchResponse {
+    #[serde(default)]
+    items: Vec<Item>,
+}
+
+#[derive(Deserialize)]
+struct SelectionResponse {
+    #[serde(rename = "batchrsp")]
+    batch_response: BatchResponse,
+}
+
+/// Client for the Windows Spotlight content API.
+pub struct Client {
+    endpoint: Url,
+    client: reqwest::Client,
+}
+
+impl Default for Client {
+    fn default() -> Self {
+        Self::new(Url::parse(DEFAULT_ENDPOINT).unwrap())
+    }
+}
+
+impl Client {
+    pub fn new(endpoint: Url) -> Self {
+        Self {
+            endpoint,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    pub fn endpoint(&self) -> &Url {
+        &self.endpoint
+    }
+
+    /// Returns images for the specified locale and orientation.
+    pub async fn get_images(&self, locale: &str, orientation: Orientation) -> Result<Vec<Image>, Box<dyn Error>> {
+        let request = self
+            .client
+            .get(self.endpoint.as_ref())
+            .query(&Query::new(locale))
+            .build()?;
+
+        let resp = self.client.execute(request).await?;
+
+        if !resp.status().is_success() {
+            return Err(format!("failed to get selection response: {}", resp.status()).into());
+        }
+
+        let images = resp
+            .json::<SelectionResponse>()
+            .await?
+            .batch_response
+            .items
+            .into_iter()
+            .filter_map(|item| match serde_json::from_str::<ItemContent>(&item.item) {
+                Ok(content) => Some(content.ad),
+                Err(e) => {
+                    eprintln!("failed to parse item: {e}");
+                    None
+                }
+            })
+            .map(|ad| Image {
+                url: match orientation {
+                    Orientation::Landscape => ad.landscape_image.asset,
+                    Orientation::Portrait => ad.portrait_image.asset,
+                },
+                title: ad.title,
+                copyright: ad.copyright,
+                description: ad.description,
+                link: Url::parse(ad.cta_uri.trim_start_matches("microsoft-edge:")).ok(),
+            })
+            .collect::<Vec<_>>();
+
+        Ok(images)
+    }
+
+    /// Downloads images to a specified directory and records them in the catalog.
+    pub async fn copy_images_to(
+        &self,
+        dst: impl AsRef<Path>,
+        locale: &str,
+        orientation: Orientation,
+        save: &SaveOptions,
+    ) -> Result<Report, Box<dyn Error>> {
+        let dst = dst.as_ref();
+        let library = Library::open(dst)?;
+
+        // Images are downloaded first, and named and filed once their content and dimensions are known.
+        let downloads = library.downloads_dir();
+        fs::create_dir_all(&downloads)?;
+
+        let tasks = self
+            .get_images(locale, orientation)
+            .await?
+            .into_iter()
+            .filter_map(|image| {
+                let id = image.id()?;
+                if library.catalog().is_pruned(Source::Spotlight, &id).unwrap_or(false) {
+                    return None;
+                }
+                // Saved images may have been renamed or converted since.
+                let existing = library.find_by_source(Source::Spotlight, &id).ok().flatten();
+                let dst = downloads.join(id);
+
+                Some(tokio::spawn(async move {
+                    if let Some(entry) = existing {
+                        return (image, Ok(Saved::Existing(entry.path)));
+                    }
+
+                    let result = util::download_file(&image.url, dst)
+                        .await
+                        .map_err(|e| format!("failed to download {}: {}", image.url, e));
+                    (image, result)
+                }))
+            });
+
+        let mut report = Report::default();
+
+        for result in futures::future::join_all(tasks).await {
+            let (image, saved) = match result {
+                Ok((image, Ok(saved))) => (image, saved),
+                Ok((_, Err(err))) => {
+                    report.failed += 1;
+                    eprintln!("{err}");
+                    continue;
+                }
+                Err(err) => {
+                    report.failed += 1;
+                    eprintln!("failed to download image: {err}");
+                    continue;
+                }
+            };
+
+            let metadata = image.metadata(locale);
+            let saved = if saved.path().starts_with(&downloads) {
+                match library.place(saved.path(), &metadata, save.template.as_ref(), save.layout, false) {
+                    Ok(saved) => saved,
+                    Err(err) => {
+                        report.failed += 1;
+                        eprintln!("failed to name {}: {}", saved.path().display(), err);
+                        continue;
+                    }
+                }
+            } else {
+                saved
+            };
+
+            match library.store(metadata, &saved, save) {
+                Ok(_) => report.add(&saved),
+                Err(err) => {
+                    report.failed += 1;
+                    eprintln!("failed to add {} to catalog: {}", saved.path().display(), err);
+                }
+            }
+        }
+
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{method, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn item(title: &str) -> serde_json::Value {
+        let content = serde_json::json!({
+            "ad": {
+                "landscapeImage": { "asset": format!("https://img.example.com/{title}-landscape.jpg") },
+                "portraitImage": { "asset": format!("https://img.example.com/{title}-portrait.jpg") },
+                "title": title,
+                "copyright": "© Photographer",
+            }
+        });
+
+        serde_json::json!({ "item": content.to_string() })
+    }
+
+    #[tokio::test]
+    async fn test_get_images() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(query_param("locale", "ja-JP"))
+            .and(query_param("country", "JP"))
+            .and(query_param("placement", PLACEMENT))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "batchrsp": { "items": [item("Lake"), item("Forest")] }
+            })))
+            .mount(&server)
+            .await;
+
+        let client = Client::new(Url::parse(&server.uri()).unwrap());
+
+        let images = client.get_images("ja-JP", Orientation::Portrait).await.unwrap();
+        assert_eq!(images.len(), 2);
+        assert_eq!(images[0].title, "Lake");
+        assert_eq!(images[0].copyright, "© Photographer");
+        assert_eq!(images[0].url.as_str(), "https://img.example.com/Lake-portrait.jpg");
+        assert_eq!(images[1].id().as_deref(), Some("Forest-portrait.jpg"));
+    }
+}
@@ -0,0 +1,142 @@
+use notify_debouncer_mini::notify::{RecommendedWatcher, RecursiveMode};
+use notify_debouncer_mini::{DebounceEventResult, Debouncer, new_debouncer};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::config::SaveOptions;
+use crate::filter::Filter;
+use crate::library::Library;
+use crate::spotlight;
+use crate::util::Saved;
+
+/// Default time to wait for an asset to settle before it is checked.
+pub const DEFAULT_DEBOUNCE: Duration = Duration::from_secs(2);
+
+/// Watches asset directories and copies new images. Watching stops when it is dropped.
+pub struct Watcher {
+    _debouncer: Debouncer<RecommendedWatcher>,
+}
+
+/// Watches Windows Spotlight assets and copies new or changed images matching `filter` to a specified directory.
+///
+/// `on_copy` is called with the path of each newly written copy.
+pub fn watch(
+    dst: impl AsRef<Path>,
+    filter: Option<&Filter>,
+    save: SaveOptions,
+    debounce: Duration,
+    on_copy: impl FnMut(&Path) + Send + 'static,
+) -> Result<Watcher, Box<dyn Error>> {
+    let dirs = spotlight::get_asset_dirs().map_err(|e| format!("failed to get asset dirs: {e}"))?;
+
+    if dirs.is_empty() {
+        return Err("no asset directories found".into());
+    }
+
+    watch_dirs(&dirs, dst, filter, save, debounce, on_copy)
+}
+
+/// Watches the specified directories and copies new or changed images matching `filter` to a specified directory.
+pub fn watch_dirs(
+    dirs: &[PathBuf],
+    dst: impl AsRef<Path>,
+    filter: Option<&Filter>,
+    save: SaveOptions,
+    debounce: Duration,
+    mut on_copy: impl FnMut(&Path) + Send + 'static,
+) -> Result<Watcher, Box<dyn Error>> {
+    let dst = dst.as_ref().to_path_buf();
+    let library = Library::open(&dst)?;
+    let filter = spotlight::asset_filter(filter);
+
+    let mut debouncer = new_debouncer(debounce, move |result: DebounceEventResult| {
+        let events = match result {
+            Ok(events) => events,
+            Err(e) => {
+                eprintln!("failed to watch assets: {e}");
+                return;
+            }
+        };
+
+        for event in events {
+            let path = event.path;
+            if !path.is_file() {
+                continue;
+            }
+
+            if spotlight::is_pruned(&library, &path) {
+                continue;
+            }
+
+            match spotlight::is_image(&path, &filter) {
+                Ok(true) => {}
+                Ok(false) => continue,
+                Err(e) => {
+                    eprintln!("failed to open image: {e}");
+                    continue;
+                }
+            }
+
+            match spotlight::save_image(&library, &path, &save) {
+                Ok(Saved::New(path)) => on_copy(&path),
+                Ok(Saved::Existing(_)) => {}
+                Err(err) => eprintln!(
+                    "failed to copy image from {} to {}: {}",
+                    path.display(),
+                    dst.display(),
+                    err
+                ),
+            }
+        }
+    })?;
+
+    for dir in dirs {
+        debouncer
+            .watcher()
+            .watch(dir, RecursiveMode::NonRecursive)
+            .map_err(|e| format!("failed to watch {}: {}", dir.display(), e))?;
+    }
+
+    Ok(Watcher { _debouncer: debouncer })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util;
+    use image::RgbImage;
+    use std::fs;
+    use std::sync::mpsc;
+
+    #[test]
+    fn test_watch_dirs() {
+        let assets = tempfile::tempdir().unwrap();
+        let dst = tempfile::tempdir().unwrap();
+
+        let (tx, rx) = mpsc::channel();
+        let _watcher = watch_dirs(
+            &[assets.path().to_path_buf()],
+            dst.path(),
+            None,
+            SaveOptions::default(),
+            Duration::from_millis(100),
+            move |path| tx.send(path.to_path_buf()).unwrap(),
+        )
+        .unwrap();
+
+        // Assets appear without an extension. Write them elsewhere first, so that only complete files are seen.
+        let staging = tempfile::tempdir().unwrap();
+        for (name, width, height) in [("icon", 64, 64), ("wallpaper", 1920, 1080)] {
+            let path = staging.path().join(format!("{name}.png"));
+            RgbImage::new(width, height).save(&path).unwrap();
+            fs::rename(&path, assets.path().join(name)).unwrap();
+        }
+
+        // Only the wallpaper passes the default filter, and it is copied once.
+        let copy = rx.recv_timeout(Duration::from_secs(10)).unwrap();
+        assert!(copy.starts_with(dst.path()));
+        assert_eq!(util::get_image_dimensions(&copy).unwrap(), (1920, 1080));
+        assert!(rx.recv_timeout(Duration::from_millis(500)).is_err());
+    }
+}
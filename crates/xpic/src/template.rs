@@ -0,0 +1,340 @@
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::metadata::{Metadata, iso_date};
+use crate::util::{self, Saved};
+
+/// Placeholders a template may use.
+pub const PLACEHOLDERS: &[&str] = &[
+    "source",
+    "id",
+    "title",
+    "copyright",
+    "date",
+    "year",
+    "month",
+    "day",
+    "market",
+    "width",
+    "height",
+    "orientation",
+    "hash",
+    "ext",
+];
+
+/// Longest file name written, in bytes. Most filesystems allow 255.
+const MAX_NAME_LEN: usize = 200;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Placeholder { name: String, default: Option<String> },
+}
+
+/// Output name of a saved wallpaper, such as `{date}_{title}_{market}.{ext}` or `{source}/{hash}.{ext}`.
+///
+/// `/` separates directories. A placeholder can have a default for unknown values, e.g. `{title:untitled}`;
+/// otherwise unknown values are left out. Use `{{` and `}}` for literal braces.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub struct Template {
+    source: String,
+    segments: Vec<Segment>,
+}
+
+impl FromStr for Template {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = s.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut placeholder = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => placeholder.push(c),
+                            None => return Err(format!("unclosed placeholder in template: {s}")),
+                        }
+                    }
+
+                    let (name, default) = match placeholder.split_once(':') {
+                        Some((name, default)) => (name.trim(), Some(default.to_string())),
+                        None => (placeholder.trim(), None),
+                    };
+
+                    if !PLACEHOLDERS.contains(&name) {
+                        return Err(format!(
+                            "unknown placeholder {{{name}}}, expected one of {}",
+                            PLACEHOLDERS.join(", ")
+                        ));
+                    }
+
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    segments.push(Segment::Placeholder {
+                        name: name.to_string(),
+                        default,
+                    });
+                }
+                '}' => return Err(format!("unmatched }} in template: {s}")),
+                c => literal.push(c),
+            }
+        }
+
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+
+        Ok(Template {
+            source: s.to_string(),
+            segments,
+        })
+    }
+}
+
+impl fmt::Display for Template {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+impl From<Template> for String {
+    fn from(template: Template) -> Self {
+        template.source
+    }
+}
+
+impl TryFrom<String> for Template {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// Returns the value of a placeholder.
+fn value(name: &str, metadata: &Metadata, hash: &str, ext: &str) -> Option<String> {
+    let date = metadata.date.as_deref().map(iso_date);
+    let date_part = |i: usize| date.as_deref()?.split('-').nth(i).map(String::from);
+
+    match name {
+        "source" => Some(metadata.source.to_string()),
+        "id" => Some(metadata.id.clone()),
+        "title" => metadata.title.clone(),
+        "copyright" => metadata.copyright_holder().map(String::from),
+        "date" => date.clone(),
+        "year" => date_part(0),
+        "month" => date_part(1),
+        "day" => date_part(2),
+        "market" => metadata.market.clone(),
+        "width" => metadata.width.map(|width| width.to_string()),
+        "height" => metadata.height.map(|height| height.to_string()),
+        "orientation" => metadata.orientation().map(|orientation| orientation.to_string()),
+        "hash" => Some(hash.to_string()),
+        "ext" => Some(ext.to_string()),
+        _ => None,
+    }
+    .filter(|value| !value.is_empty())
+}
+
+/// Returns a file or directory name that is valid on common filesystems.
+///
+/// Path separators, characters reserved on Windows and control characters become `_`, trailing dots and spaces are
+/// removed, reserved device names such as `CON` are prefixed with `_`, and long names are shortened.
+pub fn sanitize(name: &str) -> String {
+    let mut name = name
+        .chars()
+        .map(|c| match c {
+            '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect::<String>()
+        .trim()
+        .trim_end_matches(['.', ' '])
+        .to_string();
+
+    let stem = name.split('.').next().unwrap_or_default().to_ascii_uppercase();
+    let reserved = matches!(stem.as_str(), "CON" | "PRN" | "AUX" | "NUL")
+        || (stem.len() == 4
+            && (stem.starts_with("COM") || stem.starts_with("LPT"))
+            && stem.as_bytes()[3].is_ascii_digit());
+    if reserved {
+        name.insert(0, '_');
+    }
+
+    if name.len() > MAX_NAME_LEN {
+        // Keep the extension when shortening.
+        let ext = Path::new(&name)
+            .extension()
+            .map(|ext| format!(".{}", ext.to_string_lossy()))
+            .filter(|ext| ext.len() < 16)
+            .unwrap_or_default();
+
+        let mut end = MAX_NAME_LEN - ext.len();
+        while !name.is_char_boundary(end) {
+            end -= 1;
+        }
+        name = format!("{}{}", name[..end].trim_end_matches(['.', ' ']), ext);
+    }
+
+    if name.is_empty() || name.chars().all(|c| c == '.') {
+        "_".to_string()
+    } else {
+        name
+    }
+}
+
+impl Template {
+    /// Returns the relative path of an image with the metadata, content hash and extension.
+    ///
+    /// Every directory and file name is sanitized, so placeholder values cannot add directories or leave the
+    /// output directory.
+    pub fn render(&self, metadata: &Metadata, hash: &str, ext: &str) -> PathBuf {
+        let mut rendered = String::new();
+
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(literal) => rendered.push_str(literal),
+                Segment::Placeholder { name, default } => {
+                    let value = value(name, metadata, hash, ext).or_else(|| default.clone());
+                    // Separators in values must not create directories.
+                    rendered.push_str(&value.unwrap_or_default().replace(['/', '\\'], "_"));
+                }
+            }
+        }
+
+        rendered
+            .split(['/', '\\'])
+            .filter(|component| !component.is_empty())
+            .map(sanitize)
+            .collect()
+    }
+}
+
+/// Returns `path` with ` (n)` appended to its file stem.
+pub(crate) fn numbered(path: &Path, n: usize) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+
+    match path.extension() {
+        Some(ext) => path.with_file_name(format!("{stem} ({n}).{}", ext.to_string_lossy())),
+        None => path.with_file_name(format!("{stem} ({n})")),
+    }
+}
+
+/// Copies or moves a file to `dst`, creating parent directories.
+///
+/// If `dst` is taken by the same content, nothing is written and the existing path is returned. If it is taken by
+/// other content, ` (2)`, ` (3)` and so on are appended to the name until a free or identical file is found, so
+/// that no file is ever overwritten.
+pub fn place(src: impl AsRef<Path>, dst: impl AsRef<Path>, keep_src: bool) -> Result<Saved, Box<dyn Error>> {
+    let src = src.as_ref();
+    let hash = util::hash_file(src)?;
+
+    if let Some(parent) = dst.as_ref().parent() {
+        fs::create_dir_all(parent).map_err(|e| format!("failed to create {}: {}", parent.display(), e))?;
+    }
+
+    let original = dst.as_ref();
+    let mut dst = original.to_path_buf();
+
+    for n in 2.. {
+        if !dst.exists() {
+            break;
+        }
+
+        if util::hash_file(&dst)? == hash {
+            if !keep_src {
+                fs::remove_file(src)?;
+            }
+            return Ok(Saved::Existing(dst));
+        }
+
+        dst = numbered(original, n);
+    }
+
+    if keep_src {
+        fs::copy(src, &dst)?;
+    } else if fs::rename(src, &dst).is_err() {
+        // Renaming fails across filesystems.
+        fs::copy(src, &dst)?;
+        fs::remove_file(src)?;
+    }
+
+    Ok(Saved::New(dst))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::Source;
+
+    #[test]
+    fn test_render() {
+        let mut metadata = Metadata::new(Source::Bing, "OHR.Lighthouse_EN-US123_UHD.jpg");
+        metadata.title = Some("Lighthouse: north/south?".to_string());
+        metadata.date = Some("20240131".to_string());
+        metadata.market = Some("EN-US".to_string());
+
+        let template = "{date}_{title}_{market}.{ext}".parse::<Template>().unwrap();
+        assert_eq!(
+            template.render(&metadata, "abc", "jpg"),
+            PathBuf::from("2024-01-31_Lighthouse_ north_south__EN-US.jpg")
+        );
+
+        let template = "{source}/{year}/{copyright:unknown}-{hash}.{ext}"
+            .parse::<Template>()
+            .unwrap();
+        assert_eq!(
+            template.render(&metadata, "abc", "jpg"),
+            PathBuf::from("bing/2024/unknown-abc.jpg")
+        );
+
+        let template = "../{title}/con.{ext}".parse::<Template>().unwrap();
+        assert_eq!(
+            template.render(&metadata, "abc", "jpg"),
+            ["_", "Lighthouse_ north_south_", "_con.jpg"]
+                .iter()
+                .collect::<PathBuf>()
+        );
+
+        assert!("{nope}".parse::<Template>().is_err());
+        assert!("{title".parse::<Template>().is_err());
+        assert_eq!(sanitize(&"a".repeat(300)).len(), MAX_NAME_LEN);
+    }
+
+    #[test]
+    fn test_place() {
+        let dir = tempfile::tempdir().unwrap();
+        let (a, b) = (dir.path().join("a"), dir.path().join("b"));
+        fs::write(&a, "a").unwrap();
+        fs::write(&b, "b").unwrap();
+
+        let dst = dir.path().join("out").join("name.jpg");
+        assert_eq!(place(&a, &dst, true).unwrap(), Saved::New(dst.clone()));
+        assert_eq!(place(&a, &dst, true).unwrap(), Saved::Existing(dst.clone()));
+
+        let numbered = dir.path().join("out").join("name (2).jpg");
+        assert_eq!(place(&b, &dst, false).unwrap(), Saved::New(numbered.clone()));
+        assert!(!b.exists());
+        assert_eq!(fs::read_to_string(numbered).unwrap(), "b");
+    }
+}
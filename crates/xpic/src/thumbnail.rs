@@ -0,0 +1,258 @@
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::catalog::Entry;
+use crate::library::Library;
+use crate::util;
+
+/// Directory of thumbnails, inside the data directory of a library.
+pub const THUMBNAILS_DIR: &str = "thumbnails";
+
+/// Encoding of thumbnails.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    #[default]
+    Jpeg,
+    /// Smaller than JPEG at the same quality.
+    Webp,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "jpeg" | "jpg" => Ok(Format::Jpeg),
+            "webp" => Ok(Format::Webp),
+            _ => Err(format!("unknown thumbnail format: {s}")),
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Format::Jpeg => write!(f, "jpeg"),
+            Format::Webp => write!(f, "webp"),
+        }
+    }
+}
+
+impl Format {
+    fn extension(self) -> &'static str {
+        match self {
+            Format::Jpeg => "jpg",
+            Format::Webp => "webp",
+        }
+    }
+}
+
+/// Size and encoding of thumbnails.
+///
+/// ```toml
+/// [thumbnail]
+/// width = 480
+/// height = 270
+/// format = "webp"
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Thumbnail {
+    /// Thumbnails cover this box, keeping the aspect ratio of the image.
+    pub width: u32,
+    pub height: u32,
+    pub format: Format,
+    /// JPEG or WebP quality, from 1 to 100.
+    pub quality: u8,
+}
+
+impl Default for Thumbnail {
+    /// Twice the size of a gallery tile, for high-DPI displays.
+    fn default() -> Self {
+        Self {
+            width: 480,
+            height: 270,
+            format: Format::default(),
+            quality: 80,
+        }
+    }
+}
+
+impl Thumbnail {
+    /// Returns the directory of thumbnails of this size and encoding.
+    fn dir(&self, library: &Library) -> PathBuf {
+        let name = format!("{}x{}-q{}", self.width, self.height, self.quality);
+
+        library.data_dir().join(THUMBNAILS_DIR).join(name)
+    }
+
+    /// Returns where the thumbnail of content with the hash is cached.
+    pub fn path(&self, library: &Library, hash: &str) -> PathBuf {
+        self.dir(library).join(hash).with_extension(self.format.extension())
+    }
+
+    /// Writes a thumbnail of an image to `dst`.
+    pub fn generate(&self, src: impl AsRef<Path>, dst: impl AsRef<Path>) -> Result<(), Box<dyn Error>> {
+        let img = util::open_image(src)?;
+
+        // Scale so that the thumbnail covers the box; never enlarge.
+        let scale = f64::max(
+            self.width as f64 / img.width() as f64,
+            self.height as f64 / img.height() as f64,
+        );
+        let img = if scale < 1.0 {
+            let width = ((img.width() as f64 * scale).round() as u32).max(1);
+            let height = ((img.height() as f64 * scale).round() as u32).max(1);
+            img.resize_exact(width, height, FilterType::Triangle)
+        } else {
+            img
+        };
+
+        let dst = dst.as_ref();
+        if let Some(parent) = dst.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        // An interrupted write is not mistaken for a thumbnail. Entries with the same content share a thumbnail, so it
+        // may be generated twice at the same time.
+        util::write_atomic(dst, |tmp| {
+            match self.format {
+                Format::Jpeg => {
+                    let writer = BufWriter::new(File::create(tmp)?);
+                    img.to_rgb8()
+                        .write_with_encoder(JpegEncoder::new_with_quality(writer, self.quality))?
+                }
+                Format::Webp => {
+                    // The image crate only encodes lossless WebP.
+                    let rgba = img.to_rgba8();
+                    let data = webp::Encoder::from_rgba(&rgba, rgba.width(), rgba.height())
+                        .encode(self.quality.clamp(1, 100) as f32);
+                    fs::write(tmp, &*data)?;
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// Returns the path of the thumbnail of an entry, generating it if it is missing or older than the image.
+    pub fn get(&self, library: &Library, entry: &Entry) -> Result<PathBuf, Box<dyn Error>> {
+        let path = self.path(library, &entry.hash);
+
+        let fresh = match (fs::metadata(&path), fs::metadata(&entry.path)) {
+            (Ok(thumbnail), Ok(image)) => thumbnail.modified()? >= image.modified()?,
+            _ => false,
+        };
+        if !fresh {
+            self.generate(&entry.path, &path)
+                .map_err(|e| format!("failed to generate thumbnail of {}: {}", entry.path.display(), e))?;
+        }
+
+        Ok(path)
+    }
+}
+
+/// Removes cached thumbnails of every size whose content is no longer in the library, and returns how many were
+/// removed.
+pub fn clean(library: &Library) -> Result<usize, Box<dyn Error>> {
+    let dir = library.data_dir().join(THUMBNAILS_DIR);
+    if !dir.exists() {
+        return Ok(0);
+    }
+
+    let hashes = library
+        .list()?
+        .into_iter()
+        .map(|entry| entry.hash)
+        .collect::<HashSet<_>>();
+
+    let mut removed = 0;
+    for size in fs::read_dir(&dir)? {
+        let size = size?.path();
+        if !size.is_dir() {
+            continue;
+        }
+
+        for thumbnail in fs::read_dir(&size)? {
+            let thumbnail = thumbnail?.path();
+            let hash = thumbnail
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default();
+
+            if !hashes.contains(&hash) {
+                fs::remove_file(&thumbnail)?;
+                removed += 1;
+            }
+        }
+    }
+
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::{Metadata, Source};
+    use image::RgbImage;
+
+    #[test]
+    fn test_thumbnail() {
+        let dir = tempfile::tempdir().unwrap();
+        let library = Library::open(dir.path()).unwrap();
+
+        let path = dir.path().join("image.png");
+        RgbImage::from_fn(960, 600, |x, y| image::Rgb([x as u8, y as u8, 0]))
+            .save(&path)
+            .unwrap();
+        let entry = library.add(Metadata::new(Source::Spotlight, "image"), &path).unwrap();
+
+        for format in [Format::Jpeg, Format::Webp] {
+            let thumbnail = Thumbnail {
+                format,
+                ..Default::default()
+            };
+
+            let cached = thumbnail.get(&library, &entry).unwrap();
+            assert_eq!(cached, thumbnail.path(&library, &entry.hash));
+            // 960x600 scaled to cover 480x270.
+            assert_eq!(util::get_image_dimensions(&cached).unwrap(), (480, 300));
+        }
+
+        // Thumbnails of another quality are cached separately.
+        let low = Thumbnail {
+            quality: 50,
+            ..Default::default()
+        };
+        assert_ne!(
+            low.path(&library, &entry.hash),
+            Thumbnail::default().path(&library, &entry.hash)
+        );
+
+        // WebP thumbnails are lossy, and smaller at a lower quality.
+        let sizes = [10, 95].map(|quality| {
+            let dst = dir.path().join(format!("q{quality}.webp"));
+            Thumbnail {
+                format: Format::Webp,
+                quality,
+                ..Default::default()
+            }
+            .generate(&path, &dst)
+            .unwrap();
+            assert!(util::is_lossy_webp(&dst).unwrap());
+            fs::metadata(&dst).unwrap().len()
+        });
+        assert!(sizes[0] < sizes[1]);
+
+        library.remove(&entry).unwrap();
+        assert_eq!(clean(&library).unwrap(), 2);
+    }
+}
@@ -0,0 +1,132 @@
+use image::DynamicImage;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::util;
+
+/// CIELAB lightness below which a pixel is dark.
+const DARK_LIGHTNESS: f64 = 40.0;
+
+/// CIELAB lightness above which a pixel is light.
+const LIGHT_LIGHTNESS: f64 = 60.0;
+
+/// Share of the image that must be dark or light for the whole image to be.
+const MAJORITY: f64 = 0.6;
+
+/// Largest share of the image that may be of the other tone.
+const MINORITY: f64 = 0.15;
+
+/// Overall brightness of a wallpaper.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Tone {
+    Dark,
+    Light,
+    /// Large dark and light areas, such as a bright sky over a dark landscape.
+    Mixed,
+}
+
+impl FromStr for Tone {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "dark" => Ok(Tone::Dark),
+            "light" => Ok(Tone::Light),
+            "mixed" => Ok(Tone::Mixed),
+            _ => Err(format!("unknown tone: {s}")),
+        }
+    }
+}
+
+impl fmt::Display for Tone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Tone::Dark => write!(f, "dark"),
+            Tone::Light => write!(f, "light"),
+            Tone::Mixed => write!(f, "mixed"),
+        }
+    }
+}
+
+impl Tone {
+    /// Returns the tone of the Bing `drk` hint.
+    pub fn from_hint(dark: bool) -> Self {
+        if dark { Tone::Dark } else { Tone::Light }
+    }
+
+    /// Classifies an image by the shares of dark and light pixels of a downscaled copy.
+    pub fn measure(img: &DynamicImage) -> Self {
+        let rgb = img.thumbnail(64, 64).to_rgb8();
+        let total = rgb.pixels().len().max(1) as f64;
+
+        let (mut dark, mut light) = (0.0, 0.0);
+        for pixel in rgb.pixels() {
+            let lightness = lightness(pixel.0);
+            if lightness < DARK_LIGHTNESS {
+                dark += 1.0;
+            } else if lightness > LIGHT_LIGHTNESS {
+                light += 1.0;
+            }
+        }
+        let (dark, light) = (dark / total, light / total);
+
+        if dark >= MAJORITY && light <= MINORITY {
+            Tone::Dark
+        } else if light >= MAJORITY && dark <= MINORITY {
+            Tone::Light
+        } else {
+            Tone::Mixed
+        }
+    }
+}
+
+/// Returns the CIELAB lightness of an sRGB color, from 0 to 100.
+fn lightness(rgb: [u8; 3]) -> f64 {
+    let [r, g, b] = rgb.map(|c| {
+        let c = c as f64 / 255.0;
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    });
+    let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+
+    if y > 0.008856 {
+        116.0 * y.cbrt() - 16.0
+    } else {
+        903.3 * y
+    }
+}
+
+/// Returns the measured tone of an image file.
+pub fn tone(path: impl AsRef<Path>) -> Result<Tone, Box<dyn Error>> {
+    Ok(Tone::measure(&util::open_image(path)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    #[test]
+    fn test_measure() {
+        let image = |f: fn(u32) -> [u8; 3]| DynamicImage::ImageRgb8(RgbImage::from_fn(100, 100, |_, y| Rgb(f(y))));
+
+        // A night sky with a small moon.
+        assert_eq!(
+            Tone::measure(&image(|y| if y < 5 { [240; 3] } else { [15, 20, 40] })),
+            Tone::Dark
+        );
+        assert_eq!(Tone::measure(&image(|_| [230, 235, 240])), Tone::Light);
+        // A bright sky over a dark landscape.
+        assert_eq!(
+            Tone::measure(&image(|y| if y < 50 { [180, 210, 250] } else { [30, 40, 20] })),
+            Tone::Mixed
+        );
+    }
+}
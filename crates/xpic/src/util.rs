@@ -1,18 +1,100 @@
-use image::{DynamicImage, ImageFormat, ImageReader};
+use image::codecs::jpeg::JpegEncoder;
+use image::{DynamicImage, ImageBuffer, ImageFormat, ImageReader};
+use img_parts::jpeg::{JpegSegment, markers};
+use img_parts::png::PngChunk;
+use img_parts::riff::{RiffChunk, RiffContent};
+use img_parts::webp::{CHUNK_ALPH, CHUNK_EXIF, CHUNK_ICCP, CHUNK_VP8L, CHUNK_VP8X, CHUNK_XMP, WebP};
+use img_parts::{Bytes, DynImage, ImageEXIF, ImageICC};
+use jpeg_decoder::PixelFormat;
+use sha2::{Digest, Sha256};
 use std::error::Error;
 use std::fs::File;
-use std::io::BufReader;
+use std::io::{BufReader, BufWriter, Read};
 use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::{fs, io};
 use url::Url;
 
+use crate::dedup::Dropped;
+
+/// JPEG and lossy WebP quality of images derived from saved images, such as resized or captioned copies.
+const DERIVED_QUALITY: u8 = 90;
+
+/// Prefix of the APP1 segment that holds the XMP packet of a JPEG image.
+const JPEG_XMP_PREFIX: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+
+/// Type of PNG chunks with international text, one of which holds the XMP packet.
+const CHUNK_ITXT: [u8; 4] = *b"iTXt";
+
+/// Prefix of the `iTXt` chunk that holds the uncompressed XMP packet of a PNG image: the keyword, no compression and
+/// no language.
+const PNG_XMP_PREFIX: &[u8] = b"XML:com.adobe.xmp\0\0\0\0\0";
+
+/// Outcome of saving a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Saved {
+    /// The file was written.
+    New(PathBuf),
+    /// The file was already present.
+    Existing(PathBuf),
+}
+
+impl Saved {
+    pub fn path(&self) -> &Path {
+        match self {
+            Saved::New(path) | Saved::Existing(path) => path,
+        }
+    }
+
+    pub fn is_new(&self) -> bool {
+        matches!(self, Saved::New(_))
+    }
+}
+
+/// Counts of saved files.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Report {
+    pub new: usize,
+    pub existing: usize,
+    pub failed: usize,
+    /// Duplicates that were not saved.
+    pub dropped: Vec<Dropped>,
+}
+
+impl Report {
+    pub fn add(&mut self, saved: &Saved) {
+        match saved {
+            Saved::New(_) => self.new += 1,
+            Saved::Existing(_) => self.existing += 1,
+        }
+    }
+}
+
 /// Returns image reader with guessed format.
 fn new_image_reader(path: impl AsRef<Path>) -> Result<ImageReader<BufReader<File>>, Box<dyn Error>> {
     let file = File::open(&path).map_err(|e| format!("failed to open file: {e}"))?;
 
-    ImageReader::new(BufReader::new(file))
+    let mut reader = ImageReader::new(BufReader::new(file))
         .with_guessed_format()
-        .map_err(|e| format!("failed to read image: {e}").into())
+        .map_err(|e| format!("failed to read image: {e}"))?;
+
+    // The image crate only recognizes some `ftyp` boxes of AVIF images, not including the one its encoder writes.
+    if reader.format().is_none() && is_avif(&path)? {
+        reader.set_format(ImageFormat::Avif);
+    }
+
+    Ok(reader)
+}
+
+/// Returns whether a file starts with the `ftyp` box of an AVIF image.
+fn is_avif(path: impl AsRef<Path>) -> Result<bool, Box<dyn Error>> {
+    let mut header = [0; 12];
+    match File::open(path)?.read_exact(&mut header) {
+        Ok(()) => Ok(&header[4..8] == b"ftyp" && &header[8..12] == b"avif"),
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(err) => Err(err.into()),
+    }
 }
 
 /// Opens image with guessed format.
@@ -22,6 +104,33 @@ pub fn open_image(path: impl AsRef<Path>) -> Result<DynamicImage, Box<dyn Error>
         .map_err(|e| format!("failed to decode image: {e}").into())
 }
 
+/// Opens an image scaled down to fit in a `size` by `size` box, for analysis. JPEG images are decoded at a reduced
+/// scale, which is much faster than decoding them in full.
+pub fn open_image_reduced(path: impl AsRef<Path>, size: u32) -> Result<DynamicImage, Box<dyn Error>> {
+    let path = path.as_ref();
+
+    if get_image_format(path)? == ImageFormat::Jpeg {
+        let mut decoder = jpeg_decoder::Decoder::new(BufReader::new(File::open(path)?));
+        let requested = size.min(u16::MAX as u32) as u16;
+        decoder.scale(requested, requested)?;
+        let pixels = decoder.decode()?;
+        let info = decoder.info().ok_or("failed to read JPEG header")?;
+        let (width, height) = (info.width as u32, info.height as u32);
+
+        // CMYK and 16-bit images are rare, and decoded in full below.
+        let img = match info.pixel_format {
+            PixelFormat::L8 => ImageBuffer::from_raw(width, height, pixels).map(DynamicImage::ImageLuma8),
+            PixelFormat::RGB24 => ImageBuffer::from_raw(width, height, pixels).map(DynamicImage::ImageRgb8),
+            _ => None,
+        };
+        if let Some(img) = img {
+            return Ok(img.thumbnail(size, size));
+        }
+    }
+
+    Ok(open_image(path)?.thumbnail(size, size))
+}
+
 /// Returns image format.
 pub fn get_image_format(path: impl AsRef<Path>) -> Result<ImageFormat, Box<dyn Error>> {
     new_image_reader(path)?
@@ -29,8 +138,284 @@ pub fn get_image_format(path: impl AsRef<Path>) -> Result<ImageFormat, Box<dyn E
         .ok_or_else(|| "failed to get image format".into())
 }
 
+/// Returns image dimensions without decoding the image.
+pub fn get_image_dimensions(path: impl AsRef<Path>) -> Result<(u32, u32), Box<dyn Error>> {
+    let reader = new_image_reader(&path)?;
+
+    // The image crate only encodes AVIF, so its dimensions are read from the container instead.
+    if reader.format() == Some(ImageFormat::Avif) {
+        return get_avif_dimensions(path);
+    }
+
+    reader
+        .into_dimensions()
+        .map_err(|e| format!("failed to get image dimensions: {e}").into())
+}
+
+/// Returns dimensions of an AVIF image from the `ispe` property of its `meta` box.
+fn get_avif_dimensions(path: impl AsRef<Path>) -> Result<(u32, u32), Box<dyn Error>> {
+    let data = fs::read(path)?;
+
+    // `meta` and `ispe` are full boxes, whose content starts with a version and flags.
+    let ispe = find_box(&data, b"meta")
+        .and_then(|meta| find_box(meta.get(4..)?, b"iprp"))
+        .and_then(|iprp| find_box(iprp, b"ipco"))
+        .and_then(|ipco| find_box(ipco, b"ispe"))
+        .and_then(|ispe| ispe.get(4..12))
+        .ok_or("failed to get image dimensions: no ispe box")?;
+
+    let width = u32::from_be_bytes(ispe[0..4].try_into()?);
+    let height = u32::from_be_bytes(ispe[4..8].try_into()?);
+
+    Ok((width, height))
+}
+
+/// Returns content of the first ISO BMFF box of a type among sibling boxes.
+fn find_box<'a>(mut data: &'a [u8], kind: &[u8; 4]) -> Option<&'a [u8]> {
+    while data.len() >= 8 {
+        let (header, size) = match u32::from_be_bytes(data[0..4].try_into().ok()?) {
+            0 => (8, data.len()),
+            1 => (16, u64::from_be_bytes(data.get(8..16)?.try_into().ok()?) as usize),
+            size => (8, size as usize),
+        };
+        if size < header || size > data.len() {
+            return None;
+        }
+
+        if &data[4..8] == kind {
+            return Some(&data[header..size]);
+        }
+        data = &data[size..];
+    }
+
+    None
+}
+
+/// Returns the preferred extension of image.
+pub fn get_image_extension(path: impl AsRef<Path>) -> Result<&'static str, Box<dyn Error>> {
+    get_image_format(path)?
+        .extensions_str()
+        .first()
+        .copied()
+        .ok_or_else(|| "failed to get image extension".into())
+}
+
+/// Returns whether a WebP file is lossy, i.e. its image is in a `VP8 ` chunk rather than a lossless `VP8L` one.
+pub fn is_lossy_webp(path: impl AsRef<Path>) -> Result<bool, Box<dyn Error>> {
+    let data = fs::read(path)?;
+    if data.get(..4) != Some(b"RIFF") || data.get(8..12) != Some(b"WEBP") {
+        return Err("not a WebP file".into());
+    }
+
+    let mut offset = 12;
+    while let Some(header) = data.get(offset..offset + 8) {
+        match &header[..4] {
+            b"VP8 " => return Ok(true),
+            b"VP8L" => return Ok(false),
+            _ => {}
+        }
+        let size = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as usize;
+        // Chunks are padded to an even size.
+        offset = offset.saturating_add(8 + size + size % 2);
+    }
+
+    Err("no image in WebP file".into())
+}
+
+/// Writes an image made from the image at `src` to `dst` in the same format, keeping EXIF data and the ICC profile.
+pub fn save_derived_image(
+    img: &DynamicImage,
+    src: impl AsRef<Path>,
+    dst: impl AsRef<Path>,
+) -> Result<(), Box<dyn Error>> {
+    let (src, dst) = (src.as_ref(), dst.as_ref());
+    let format = get_image_format(src)?;
+
+    let lossy_webp = format == ImageFormat::WebP && is_lossy_webp(src)?;
+
+    write_atomic(dst, |tmp| {
+        match format {
+            ImageFormat::Jpeg => {
+                let writer = BufWriter::new(File::create(tmp)?);
+                img.to_rgb8()
+                    .write_with_encoder(JpegEncoder::new_with_quality(writer, DERIVED_QUALITY))?
+            }
+            // The image crate only encodes lossless WebP, which is many times larger than a lossy original.
+            ImageFormat::WebP if lossy_webp => {
+                let rgba = img.to_rgba8();
+                let data = webp::Encoder::from_rgba(&rgba, rgba.width(), rgba.height()).encode(DERIVED_QUALITY as f32);
+                fs::write(tmp, &*data)?;
+            }
+            format => img.save_with_format(tmp, format)?,
+        }
+        copy_metadata(src, tmp)
+    })
+}
+
+/// Copies EXIF data, the ICC profile and the XMP packet of `src` into `dst`, where both formats can carry them.
+///
+/// Only JPEG, PNG and WebP carry metadata. IPTC data and XMP compressed in PNG are not carried over.
+pub(crate) fn copy_metadata(src: &Path, dst: &Path) -> Result<(), Box<dyn Error>> {
+    let Some(original) = DynImage::from_bytes(fs::read(src)?.into())? else {
+        return Ok(());
+    };
+    let (exif, icc_profile, xmp) = (original.exif(), original.icc_profile(), get_xmp(&original));
+    if exif.is_none() && icc_profile.is_none() && xmp.is_none() {
+        return Ok(());
+    }
+
+    let Some(mut converted) = DynImage::from_bytes(fs::read(dst)?.into())? else {
+        return Ok(());
+    };
+    converted.set_exif(exif.clone());
+    converted.set_icc_profile(icc_profile);
+    set_xmp(&mut converted, xmp);
+
+    if let DynImage::WebP(webp) = &mut converted {
+        // WebP stores EXIF data without the `Exif\0\0` prefix that img-parts writes.
+        if let Some(exif) = exif
+            && let Some(chunk) = webp.chunks_mut().iter_mut().find(|chunk| chunk.id() == CHUNK_EXIF)
+        {
+            *chunk.content_mut() = RiffContent::Data(exif);
+        }
+        set_webp_flags(webp)?;
+    }
+    converted.encoder().write_to(File::create(dst)?)?;
+
+    Ok(())
+}
+
+/// Returns the XMP packet of an image.
+fn get_xmp(img: &DynImage) -> Option<Bytes> {
+    match img {
+        DynImage::Jpeg(jpeg) => jpeg.segments_by_marker(markers::APP1).find_map(|segment| {
+            let contents = segment.contents();
+            contents
+                .starts_with(JPEG_XMP_PREFIX)
+                .then(|| contents.slice(JPEG_XMP_PREFIX.len()..))
+        }),
+        DynImage::Png(png) => png.chunks_by_type(CHUNK_ITXT).find_map(|chunk| {
+            let contents = chunk.contents();
+            contents
+                .starts_with(PNG_XMP_PREFIX)
+                .then(|| contents.slice(PNG_XMP_PREFIX.len()..))
+        }),
+        DynImage::WebP(webp) => webp.chunk_by_id(CHUNK_XMP)?.content().data().cloned(),
+    }
+}
+
+/// Replaces the XMP packet of an image.
+fn set_xmp(img: &mut DynImage, xmp: Option<Bytes>) {
+    match img {
+        DynImage::Jpeg(jpeg) => {
+            let segments = jpeg.segments_mut();
+            segments.retain(|segment| {
+                segment.marker() != markers::APP1 || !segment.contents().starts_with(JPEG_XMP_PREFIX)
+            });
+            if let Some(xmp) = xmp {
+                // XMP goes with the other application segments, before the image data.
+                let pos = segments
+                    .iter()
+                    .rposition(|segment| (markers::APP0..=markers::APP15).contains(&segment.marker()))
+                    .map_or(0, |pos| pos + 1);
+                let segment = JpegSegment::new_with_contents(markers::APP1, [JPEG_XMP_PREFIX, &xmp].concat().into());
+                segments.insert(pos, segment);
+            }
+        }
+        DynImage::Png(png) => {
+            let chunks = png.chunks_mut();
+            chunks.retain(|chunk| chunk.kind() != CHUNK_ITXT || !chunk.contents().starts_with(PNG_XMP_PREFIX));
+            if let Some(xmp) = xmp {
+                // Before the closing `IEND` chunk.
+                let chunk = PngChunk::new(CHUNK_ITXT, [PNG_XMP_PREFIX, &xmp].concat().into());
+                chunks.insert(chunks.len().saturating_sub(1), chunk);
+            }
+        }
+        DynImage::WebP(webp) => {
+            webp.remove_chunks_by_id(CHUNK_XMP);
+            if let Some(xmp) = xmp {
+                webp.chunks_mut()
+                    .push(RiffChunk::new(CHUNK_XMP, RiffContent::Data(xmp)));
+            }
+        }
+    }
+}
+
+/// Sets the flags of the `VP8X` chunk of a WebP image from the chunks it has, adding a `VP8X` chunk if one is needed.
+///
+/// img-parts only adds the chunk for EXIF data and ICC profiles, and leaves the flags of an existing one alone.
+fn set_webp_flags(webp: &mut WebP) -> Result<(), Box<dyn Error>> {
+    // The alpha bit of the lossless bitstream header follows the signature byte and 28 bits of dimensions.
+    let lossless_alpha = webp
+        .chunk_by_id(CHUNK_VP8L)
+        .and_then(|chunk| chunk.content().data()?.get(1..5)?.try_into().ok())
+        .is_some_and(|header| u32::from_le_bytes(header) & (1 << 28) != 0);
+
+    let mut flags = 0;
+    for (present, flag) in [
+        (webp.has_chunk(CHUNK_ICCP), 0x20),
+        (webp.has_chunk(CHUNK_ALPH) || lossless_alpha, 0x10),
+        (webp.has_chunk(CHUNK_EXIF), 0x08),
+        (webp.has_chunk(CHUNK_XMP), 0x04),
+    ] {
+        if present {
+            flags |= flag;
+        }
+    }
+
+    if let Some(chunk) = webp.chunks_mut().iter_mut().find(|chunk| chunk.id() == CHUNK_VP8X) {
+        let mut content = chunk.content().data().ok_or("failed to read VP8X chunk")?.to_vec();
+        *content.first_mut().ok_or("failed to read VP8X chunk")? = flags;
+        *chunk.content_mut() = RiffContent::Data(content.into());
+    } else if flags != 0 {
+        let (width, height) = webp.dimensions().ok_or("failed to get WebP dimensions")?;
+        let mut content = vec![flags, 0, 0, 0];
+        content.extend_from_slice(&(width - 1).to_le_bytes()[..3]);
+        content.extend_from_slice(&(height - 1).to_le_bytes()[..3]);
+        webp.chunks_mut()
+            .insert(0, RiffChunk::new(CHUNK_VP8X, RiffContent::Data(content.into())));
+    }
+
+    Ok(())
+}
+
+/// Writes a file with `write` to a temporary path next to `dst` and then moves it to `dst`, so that a failed or
+/// interrupted write never leaves a truncated file at `dst`.
+pub fn write_atomic(
+    dst: impl AsRef<Path>,
+    write: impl FnOnce(&Path) -> Result<(), Box<dyn Error>>,
+) -> Result<(), Box<dyn Error>> {
+    let dst = dst.as_ref();
+    let tmp = temp_path(dst);
+
+    let result = write(&tmp)
+        .and_then(|()| fs::rename(&tmp, dst).map_err(|e| format!("failed to write {}: {}", dst.display(), e).into()));
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+
+    result
+}
+
+/// Returns a temporary path next to `path`, unique within the process and among processes.
+pub fn temp_path(path: impl AsRef<Path>) -> PathBuf {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+    path.as_ref().with_extension(format!("{}-{}.tmp", process::id(), n))
+}
+
+/// Returns hex encoded SHA-256 digest of file content.
+pub fn hash_file(path: impl AsRef<Path>) -> Result<String, Box<dyn Error>> {
+    let mut file = File::open(&path).map_err(|e| format!("failed to open file: {e}"))?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)?;
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
 /// Copies image from src to dst.
-pub fn copy_image(src: impl AsRef<Path>, dst: impl AsRef<Path>, set_extension: bool) -> Result<(), Box<dyn Error>> {
+pub fn copy_image(src: impl AsRef<Path>, dst: impl AsRef<Path>, set_extension: bool) -> Result<Saved, Box<dyn Error>> {
     // If dst is a directory, append src filename to dst.
     let mut dst = if dst.as_ref().is_dir() {
         dst.as_ref()
@@ -39,29 +424,65 @@ pub fn copy_image(src: impl AsRef<Path>, dst: impl AsRef<Path>, set_extension: b
         PathBuf::from(dst.as_ref())
     };
 
-    // Check if dst exists after appending src filename if dst is a directory.
+    // Set dst extension to match src image format.
+    if set_extension {
+        dst.set_extension(get_image_extension(src.as_ref())?);
+    }
+
+    // Check if dst exists after the extension is set, so that it matches the file actually written.
     if dst.exists() {
-        return Ok(());
+        return Ok(Saved::Existing(dst));
     }
 
-    // Set dst extension to match src image format.
-    if set_extension {
-        dst.set_extension(
-            get_image_format(src.as_ref())?
-                .extensions_str()
-                .first()
-                .ok_or("failed to get image extension")?,
-        );
+    write_atomic(&dst, |tmp| {
+        fs::copy(src, tmp)?;
+        Ok(())
+    })?;
+    Ok(Saved::New(dst))
+}
+
+/// Copies image from src to dir, naming it by content hash and image format.
+pub fn copy_image_by_hash(src: impl AsRef<Path>, dir: impl AsRef<Path>) -> Result<Saved, Box<dyn Error>> {
+    let hash = hash_file(src.as_ref())?;
+    let dst = dir
+        .as_ref()
+        .join(hash)
+        .with_extension(get_image_extension(src.as_ref())?);
+
+    copy_image(src, dst, false)
+}
+
+/// Returns files under dir recursively, skipping hidden directories.
+pub fn walk_files(dir: impl AsRef<Path>) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut files = Vec::new();
+    let mut dirs = vec![dir.as_ref().to_path_buf()];
+
+    while let Some(dir) = dirs.pop() {
+        let entries = fs::read_dir(&dir).map_err(|e| format!("failed to read {}: {}", dir.display(), e))?;
+
+        for entry in entries.filter_map(Result::ok) {
+            let path = entry.path();
+
+            if path.is_dir() {
+                if !entry.file_name().to_string_lossy().starts_with('.') {
+                    dirs.push(path);
+                }
+            } else if path.is_file() {
+                files.push(path);
+            }
+        }
     }
 
-    fs::copy(src, dst)?;
-    Ok(())
+    files.sort();
+    Ok(files)
 }
 
 /// Downloads file from url to dst.
-pub async fn download_file(url: &Url, dst: impl AsRef<Path>) -> Result<(), Box<dyn Error>> {
-    if dst.as_ref().exists() {
-        return Ok(());
+pub async fn download_file(url: &Url, dst: impl AsRef<Path>) -> Result<Saved, Box<dyn Error>> {
+    let dst = dst.as_ref();
+
+    if dst.exists() {
+        return Ok(Saved::Existing(dst.to_path_buf()));
     }
 
     let resp = reqwest::get(url.as_ref()).await?;
@@ -70,9 +491,135 @@ pub async fn download_file(url: &Url, dst: impl AsRef<Path>) -> Result<(), Box<d
         return Err(format!("failed to download file from {url}").into());
     }
 
-    let mut file = File::create(dst)?;
+    // An interrupted download must not leave a file behind, as existing files are skipped.
     let content = resp.bytes().await?;
-    io::copy(&mut content.as_ref(), &mut file)?;
+    write_atomic(dst, |tmp| Ok(fs::write(tmp, &content)?))?;
 
-    Ok(())
+    Ok(Saved::New(dst.to_path_buf()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::RgbImage;
+
+    #[test]
+    fn test_copy_image_by_hash() {
+        let src = tempfile::tempdir().unwrap();
+        let dst = tempfile::tempdir().unwrap();
+
+        // Spotlight assets have no extension.
+        let asset = src.path().join("asset");
+        RgbImage::new(4, 4).save_with_format(&asset, ImageFormat::Png).unwrap();
+
+        let saved = copy_image_by_hash(&asset, dst.path()).unwrap();
+        assert!(saved.is_new());
+        assert_eq!(saved.path().extension().unwrap(), "png");
+        assert_eq!(
+            saved.path().file_stem().unwrap().to_str().unwrap(),
+            hash_file(&asset).unwrap()
+        );
+
+        let saved = copy_image_by_hash(&asset, dst.path()).unwrap();
+        assert!(!saved.is_new());
+        assert_eq!(fs::read_dir(dst.path()).unwrap().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_download_file() {
+        use wiremock::matchers::path;
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(path("/image.jpg"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(b"image".to_vec()))
+            .mount(&server)
+            .await;
+        let base = Url::parse(&server.uri()).unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let dst = dir.path().join("image.jpg");
+        assert!(download_file(&base.join("missing.jpg").unwrap(), &dst).await.is_err());
+        assert!(!dst.exists());
+
+        let saved = download_file(&base.join("image.jpg").unwrap(), &dst).await.unwrap();
+        assert!(saved.is_new());
+        assert_eq!(fs::read(&dst).unwrap(), b"image");
+        // Only the downloaded file is left, without temporary files.
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_open_image_reduced() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("image.jpg");
+        RgbImage::from_fn(1024, 512, |x, _| image::Rgb([(x / 4) as u8, 0, 0]))
+            .save(&path)
+            .unwrap();
+
+        let img = open_image_reduced(&path, 64).unwrap();
+        assert_eq!((img.width(), img.height()), (64, 32));
+        let full = open_image(&path).unwrap().thumbnail(64, 64).to_rgb8();
+        let reduced = img.to_rgb8();
+        assert!((full.get_pixel(48, 16)[0] as i32 - reduced.get_pixel(48, 16)[0] as i32).abs() < 16);
+    }
+
+    #[test]
+    fn test_save_derived_image() {
+        let dir = tempfile::tempdir().unwrap();
+        let img = DynamicImage::ImageRgba8(image::RgbaImage::from_fn(64, 32, |x, y| {
+            image::Rgba([x as u8 * 4, y as u8 * 8, 128, 255])
+        }));
+
+        // WebP stays lossy or lossless, like the original.
+        let lossy = dir.path().join("lossy.webp");
+        fs::write(&lossy, &*webp::Encoder::from_image(&img).unwrap().encode(75.0)).unwrap();
+        let lossless = dir.path().join("lossless.webp");
+        img.save(&lossless).unwrap();
+        assert!(is_lossy_webp(&lossy).unwrap());
+        assert!(!is_lossy_webp(&lossless).unwrap());
+
+        let small = img.thumbnail(32, 16);
+        for (src, lossy) in [(lossy, true), (lossless, false)] {
+            let dst = src.with_file_name("derived.webp");
+            save_derived_image(&small, &src, &dst).unwrap();
+            assert_eq!(is_lossy_webp(&dst).unwrap(), lossy);
+            assert_eq!(get_image_dimensions(&dst).unwrap(), (32, 16));
+        }
+    }
+
+    #[test]
+    fn test_copy_metadata_xmp() {
+        let dir = tempfile::tempdir().unwrap();
+        let img = DynamicImage::ImageRgb8(RgbImage::from_fn(32, 16, |x, y| {
+            image::Rgb([x as u8 * 8, y as u8 * 16, 0])
+        }));
+        let xmp = Bytes::from_static(b"<x:xmpmeta xmlns:x=\"adobe:ns:meta/\"></x:xmpmeta>");
+
+        let src = dir.path().join("image.jpg");
+        img.save(&src).unwrap();
+        let mut jpeg = DynImage::from_bytes(fs::read(&src).unwrap().into()).unwrap().unwrap();
+        set_xmp(&mut jpeg, Some(xmp.clone()));
+        jpeg.encoder().write_to(File::create(&src).unwrap()).unwrap();
+
+        let lossy = dir.path().join("lossy.webp");
+        fs::write(&lossy, &*webp::Encoder::from_image(&img).unwrap().encode(75.0)).unwrap();
+        let lossless = dir.path().join("lossless.webp");
+        img.save(&lossless).unwrap();
+        let png = dir.path().join("image.png");
+        img.save(&png).unwrap();
+
+        for dst in [lossy, lossless, png] {
+            copy_metadata(&src, &dst).unwrap();
+
+            let converted = DynImage::from_bytes(fs::read(&dst).unwrap().into()).unwrap().unwrap();
+            assert_eq!(get_xmp(&converted), Some(xmp.clone()));
+            if let DynImage::WebP(webp) = &converted {
+                let vp8x = webp.chunk_by_id(CHUNK_VP8X).unwrap().content().data().unwrap();
+                assert_eq!(vp8x[0], 0x04);
+            }
+            let decoded = open_image(&dst).unwrap();
+            assert_eq!((decoded.width(), decoded.height()), (32, 16));
+        }
+    }
 }
@@ -0,0 +1,258 @@
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use url::Url;
+
+use crate::catalog::Entry;
+use crate::config::SaveOptions;
+use crate::library::Library;
+use crate::metadata::Source;
+use crate::spotlight;
+use crate::util::{self, Saved};
+
+/// What is wrong with a file of the library.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Problem {
+    /// The file does not exist.
+    Missing,
+    /// The file cannot be decoded, e.g. because it is truncated.
+    Corrupt(String),
+    /// The extension does not match the image format.
+    WrongExtension { expected: String },
+    /// The content differs from the catalog.
+    HashMismatch,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::Missing => write!(f, "missing"),
+            Problem::Corrupt(err) => write!(f, "corrupt: {err}"),
+            Problem::WrongExtension { expected } => write!(f, "wrong extension, expected .{expected}"),
+            Problem::HashMismatch => write!(f, "hash mismatch"),
+        }
+    }
+}
+
+/// A problem found in a file, and whether it was repaired.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Issue {
+    pub path: PathBuf,
+    pub problem: Problem,
+    pub repaired: bool,
+}
+
+/// Returns the first problem of the file of an entry.
+pub fn check(entry: &Entry) -> Option<Problem> {
+    let path = &entry.path;
+
+    if !path.exists() {
+        return Some(Problem::Missing);
+    }
+
+    let format = match util::get_image_format(path) {
+        Ok(format) => format,
+        Err(err) => return Some(Problem::Corrupt(err.to_string())),
+    };
+
+    // AVIF is only encoded, so it cannot be checked by decoding.
+    if format != image::ImageFormat::Avif
+        && let Err(err) = util::open_image(path)
+    {
+        return Some(Problem::Corrupt(err.to_string()));
+    }
+    let ext = path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default();
+    if !format.extensions_str().contains(&ext.as_str()) {
+        return Some(Problem::WrongExtension {
+            expected: format.extensions_str().first()?.to_string(),
+        });
+    }
+
+    match util::hash_file(path) {
+        Ok(hash) if hash == entry.hash => None,
+        _ => Some(Problem::HashMismatch),
+    }
+}
+
+/// Fetches a good copy of an entry into the downloads directory, from its source URL or else, for Windows Spotlight
+/// images, from the local asset it was saved from.
+async fn fetch(library: &Library, entry: &Entry) -> Result<Option<PathBuf>, Box<dyn Error>> {
+    let downloads = library.downloads_dir();
+    fs::create_dir_all(&downloads)?;
+    let dst = downloads.join(format!("{}.verify", entry.id));
+    if dst.exists() {
+        fs::remove_file(&dst)?;
+    }
+
+    if let Some(url) = &entry.metadata.url {
+        util::download_file(&Url::parse(url)?, &dst).await?;
+    } else if entry.metadata.source == Source::Spotlight {
+        // Assets are only present on Windows. They are found by name, as the cataloged hash may be of a converted or
+        // embedded copy.
+        let assets = spotlight::get_assets().unwrap_or_default();
+        match assets.into_iter().find(|asset| {
+            asset
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy() == entry.metadata.id)
+        }) {
+            Some(asset) => {
+                fs::copy(asset, &dst)?;
+            }
+            None => return Ok(None),
+        }
+    } else {
+        return Ok(None);
+    }
+
+    if let Err(err) = util::open_image(&dst) {
+        fs::remove_file(&dst)?;
+        return Err(format!("fetched copy is corrupt: {err}").into());
+    }
+
+    Ok(Some(dst))
+}
+
+/// Moves a file to the extension of its image format and updates the catalog.
+fn fix_extension(library: &Library, entry: &Entry, ext: &str) -> Result<(), Box<dyn Error>> {
+    let dst = entry.path.with_extension(ext);
+    if dst.exists() {
+        return Err(format!("{} already exists", dst.display()).into());
+    }
+
+    fs::rename(&entry.path, &dst)?;
+    library.catalog().set_path(entry.id, library.relative(&dst)?)
+}
+
+async fn repair_entry(
+    library: &Library,
+    entry: &Entry,
+    problem: &Problem,
+    save: &SaveOptions,
+) -> Result<bool, Box<dyn Error>> {
+    if let Problem::WrongExtension { expected } = problem {
+        fix_extension(library, entry, expected)?;
+        return Ok(true);
+    }
+
+    let Some(fetched) = fetch(library, entry).await? else {
+        return Ok(false);
+    };
+
+    // The path is kept, so the extension follows the cataloged file rather than the fetched one.
+    let path = Path::new(&entry.path);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if fs::rename(&fetched, path).is_err() {
+        fs::copy(&fetched, path)?;
+        fs::remove_file(&fetched)?;
+    }
+
+    library.store(entry.metadata.clone(), &Saved::New(path.to_path_buf()), save)?;
+
+    Ok(true)
+}
+
+/// Checks that every file of a library exists, decodes, has the extension of its format and the cataloged hash, and
+/// returns the problems found. With `repair`, wrong extensions are fixed and other broken files are fetched again
+/// where possible.
+pub async fn verify(library: &Library, repair: bool, save: &SaveOptions) -> Result<Vec<Issue>, Box<dyn Error>> {
+    let mut issues = Vec::new();
+
+    for entry in library.list()? {
+        let Some(problem) = check(&entry) else {
+            continue;
+        };
+
+        let repaired = repair
+            && match repair_entry(library, &entry, &problem, save).await {
+                Ok(repaired) => repaired,
+                Err(err) => {
+                    eprintln!("failed to repair {}: {}", entry.path.display(), err);
+                    false
+                }
+            };
+
+        issues.push(Issue {
+            path: entry.path,
+            problem,
+            repaired,
+        });
+    }
+
+    Ok(issues)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::Metadata;
+    use image::{ImageFormat, RgbImage};
+    use std::io::Cursor;
+    use wiremock::matchers::method;
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn png() -> Vec<u8> {
+        let mut buf = Cursor::new(Vec::new());
+        RgbImage::from_fn(8, 8, |x, y| image::Rgb([x as u8 * 32, y as u8 * 32, 0]))
+            .write_to(&mut buf, ImageFormat::Png)
+            .unwrap();
+        buf.into_inner()
+    }
+
+    #[tokio::test]
+    async fn test_verify() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(png()))
+            .mount(&server)
+            .await;
+
+        let dir = tempfile::tempdir().unwrap();
+        let library = Library::open(dir.path()).unwrap();
+
+        let (good, truncated, renamed) = (
+            dir.path().join("good.png"),
+            dir.path().join("truncated.png"),
+            dir.path().join("renamed.jpg"),
+        );
+        for path in [&good, &truncated, &renamed] {
+            fs::write(path, png()).unwrap();
+        }
+
+        let mut metadata = Metadata::new(Source::Bing, "truncated.png");
+        metadata.url = Some(server.uri());
+        library.add(metadata, &truncated).unwrap();
+        library.add(Metadata::new(Source::Bing, "good.png"), &good).unwrap();
+        library
+            .add(Metadata::new(Source::Spotlight, "renamed"), &renamed)
+            .unwrap();
+        fs::write(&truncated, &png()[..64]).unwrap();
+
+        let save = SaveOptions::default();
+        let issues = verify(&library, false, &save).await.unwrap();
+        assert_eq!(issues.len(), 2);
+        assert!(issues.iter().all(|issue| !issue.repaired));
+        assert!(
+            issues
+                .iter()
+                .any(|issue| issue.path == truncated && matches!(issue.problem, Problem::Corrupt(_)))
+        );
+        assert!(issues.iter().any(|issue| issue.path == renamed
+            && issue.problem
+                == Problem::WrongExtension {
+                    expected: "png".to_string()
+                }));
+
+        let issues = verify(&library, true, &save).await.unwrap();
+        assert!(issues.iter().all(|issue| issue.repaired));
+        assert!(verify(&library, false, &save).await.unwrap().is_empty());
+        assert!(dir.path().join("renamed.png").exists());
+    }
+}